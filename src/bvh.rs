@@ -0,0 +1,225 @@
+use std::cmp::Ordering;
+use std::f32;
+use types::*;
+
+const MAX_LEAF_SIZE: usize = 4;
+// nodes waiting to be visited while finding a hit, enough for any tree that fits in memory
+const STACK_SIZE: usize = 64;
+
+/// Axis-aligned bounding box.
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+	pub min: V3,
+	pub max: V3,
+}
+
+impl Aabb {
+	pub fn empty() -> Aabb {
+		Aabb {
+			min: V3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+			max: V3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+		}
+	}
+
+	pub fn from_points(points: &[V3]) -> Aabb {
+		points
+			.iter()
+			.fold(Aabb::empty(), |aabb, point| aabb.grow(*point))
+	}
+
+	pub fn grow(self, point: V3) -> Aabb {
+		Aabb {
+			min: V3::new(
+				self.min.x.min(point.x),
+				self.min.y.min(point.y),
+				self.min.z.min(point.z),
+			),
+			max: V3::new(
+				self.max.x.max(point.x),
+				self.max.y.max(point.y),
+				self.max.z.max(point.z),
+			),
+		}
+	}
+
+	pub fn union(self, other: Aabb) -> Aabb {
+		self.grow(other.min).grow(other.max)
+	}
+
+	pub fn centroid(&self) -> V3 {
+		(self.min + self.max) * 0.5
+	}
+
+	pub fn corners(&self) -> [V3; 8] {
+		let (min, max) = (self.min, self.max);
+		[
+			V3::new(min.x, min.y, min.z),
+			V3::new(max.x, min.y, min.z),
+			V3::new(min.x, max.y, min.z),
+			V3::new(max.x, max.y, min.z),
+			V3::new(min.x, min.y, max.z),
+			V3::new(max.x, min.y, max.z),
+			V3::new(min.x, max.y, max.z),
+			V3::new(max.x, max.y, max.z),
+		]
+	}
+
+	/// Slab test. Returns true if the ray enters the box before `t_max`.
+	pub fn hit(&self, ray: &Ray, t_max: f32) -> bool {
+		let mut t_near = 0.0_f32;
+		let mut t_far = t_max;
+
+		for axis in 0..3 {
+			let inverse = 1.0 / ray.direction[axis];
+			let mut t0 = (self.min[axis] - ray.origin[axis]) * inverse;
+			let mut t1 = (self.max[axis] - ray.origin[axis]) * inverse;
+			if inverse < 0.0 {
+				::std::mem::swap(&mut t0, &mut t1);
+			}
+			t_near = t_near.max(t0);
+			t_far = t_far.min(t1);
+			if t_far < t_near {
+				return false;
+			}
+		}
+		true
+	}
+}
+
+#[derive(Debug)]
+enum Node {
+	Leaf {
+		bounds: Aabb,
+		start: usize,
+		count: usize,
+	},
+	Interior {
+		bounds: Aabb,
+		// the left child always directly follows its parent
+		right: usize,
+	},
+}
+
+impl Node {
+	fn bounds(&self) -> &Aabb {
+		match *self {
+			Node::Leaf { ref bounds, .. } => bounds,
+			Node::Interior { ref bounds, .. } => bounds,
+		}
+	}
+}
+
+/// Bounding volume hierarchy over a list of primitives, referred to by index.
+#[derive(Debug)]
+pub struct Bvh {
+	nodes: Vec<Node>,
+	indices: Vec<usize>,
+}
+
+impl Bvh {
+	/// Build by splitting at the median centroid along the widest axis.
+	pub fn new(primitive_bounds: &[Aabb]) -> Bvh {
+		let mut bvh = Bvh {
+			nodes: Vec::new(),
+			indices: (0..primitive_bounds.len()).collect(),
+		};
+		if !primitive_bounds.is_empty() {
+			bvh.build(primitive_bounds, 0, primitive_bounds.len());
+		}
+		bvh
+	}
+
+	fn build(&mut self, primitive_bounds: &[Aabb], start: usize, end: usize) {
+		let bounds = self.indices[start..end]
+			.iter()
+			.fold(Aabb::empty(), |aabb, &i| aabb.union(primitive_bounds[i]));
+
+		if end - start <= MAX_LEAF_SIZE {
+			self.nodes.push(Node::Leaf {
+				bounds,
+				start,
+				count: end - start,
+			});
+			return;
+		}
+
+		let centroid_bounds = self.indices[start..end]
+			.iter()
+			.fold(Aabb::empty(), |aabb, &i| aabb.grow(primitive_bounds[i].centroid()));
+		let extent = centroid_bounds.max - centroid_bounds.min;
+		let axis = if extent.x > extent.y && extent.x > extent.z {
+			0
+		} else if extent.y > extent.z {
+			1
+		} else {
+			2
+		};
+
+		let mid = (start + end) / 2;
+		self.indices[start..end].select_nth_unstable_by(mid - start, |&a, &b| {
+			primitive_bounds[a].centroid()[axis]
+				.partial_cmp(&primitive_bounds[b].centroid()[axis])
+				.unwrap_or(Ordering::Equal)
+		});
+
+		let node = self.nodes.len();
+		self.nodes.push(Node::Interior { bounds, right: 0 });
+		self.build(primitive_bounds, start, mid);
+		let right_child = self.nodes.len();
+		self.build(primitive_bounds, mid, end);
+		if let Node::Interior { ref mut right, .. } = self.nodes[node] {
+			*right = right_child;
+		}
+	}
+
+	pub fn bounds(&self) -> Aabb {
+		self.nodes
+			.first()
+			.map(|node| *node.bounds())
+			.unwrap_or_else(Aabb::empty)
+	}
+
+	/// Find the closest hit, calling `intersect` with the index of each primitive whose bounds the ray passes through.
+	pub fn closest_hit<F>(&self, ray: &Ray, mut intersect: F) -> Option<HitRecord>
+	where
+		F: FnMut(usize) -> Option<HitRecord>,
+	{
+		let mut closest: Option<HitRecord> = None;
+
+		// median splits keep the tree at most log2 of the primitive count deep, and the
+		// stack never holds more than one node per level, so this never overflows
+		let mut stack = [0usize; STACK_SIZE];
+		let mut stack_len = 0;
+		if !self.nodes.is_empty() {
+			stack_len = 1;
+		}
+
+		while stack_len > 0 {
+			stack_len -= 1;
+			let node_index = stack[stack_len];
+			let t_max = closest.map(|hit| hit.t).unwrap_or(f32::INFINITY);
+			let node = &self.nodes[node_index];
+			if !node.bounds().hit(ray, t_max) {
+				continue;
+			}
+			match *node {
+				Node::Leaf { start, count, .. } => {
+					for &primitive in &self.indices[start..start + count] {
+						if let Some(hit) = intersect(primitive) {
+							if hit.t < closest.map(|hit| hit.t).unwrap_or(f32::INFINITY) {
+								closest = Some(hit);
+							}
+						}
+					}
+				}
+				Node::Interior { right, .. } => {
+					stack[stack_len] = right;
+					stack[stack_len + 1] = node_index + 1;
+					stack_len += 2;
+				}
+			}
+		}
+
+		closest
+	}
+}
@@ -0,0 +1,378 @@
+use cgmath::prelude::*;
+use cgmath::Quaternion;
+use std::f32::consts::PI;
+use std::fmt::Debug;
+use std::sync::Arc;
+use types::*;
+
+/// Maps points on the image to rays in camera space, where the camera looks
+/// down -z with +y up.
+pub trait Projection: Debug + Send + Sync {
+	/// Ray through `film`, a point on the image in [0, 1]^2, with `lens` a sample in [0, 1]^2
+	/// for projections that model an aperture. None if the point is outside the projection.
+	/// The camera sets the ray's time.
+	fn ray(&self, film: V2, lens: V2) -> Option<Ray>;
+}
+
+/// How the two eyes of a stereo camera share the image.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum StereoLayout {
+	// left eye on the left half, each eye squeezed to half width
+	SideBySide,
+	// left eye on the top half, each eye squeezed to half height
+	TopBottom,
+	// both eyes over the whole image, left through a red filter and right through cyan
+	Anaglyph,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Stereo {
+	// distance between the eyes, along the camera's x axis
+	pub interocular_distance: f32,
+	// distance at which the eyes' views line up, giving zero parallax
+	pub convergence_distance: f32,
+	pub layout: StereoLayout,
+}
+
+/// Camera placed in the world, with the projection it views through.
+#[derive(Debug, Clone)]
+pub struct Camera {
+	pub position: V3,
+	pub orientation: Quaternion<f32>,
+	pub projection: Arc<dyn Projection>,
+	pub stereo: Option<Stereo>,
+	// interval of the frame, from 0 to 1, that the shutter is open for. Objects only
+	// blur if it has some length
+	pub shutter_open: f32,
+	pub shutter_close: f32,
+}
+
+impl Default for Camera {
+	fn default() -> Camera {
+		Camera {
+			position: V3::zero(),
+			orientation: Quaternion::one(),
+			projection: Arc::new(Perspective::default()),
+			stereo: None,
+			shutter_open: 0.0,
+			shutter_close: 0.0,
+		}
+	}
+}
+
+/// How the scene camera is built, chosen by the `CAMERA` setting.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraSettings {
+	pub projection: ProjectionKind,
+	// a pair of eyes sharing the image, rather than one view
+	pub stereo: Option<Stereo>,
+	// interval of the frame, from 0 to 1, the shutter is open for
+	pub shutter_open: f32,
+	pub shutter_close: f32,
+}
+
+/// Projection the scene camera views through.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum ProjectionKind {
+	// thin lens, focused `focus_distance` in front of the camera. An aperture radius of 0
+	// is a pinhole, with everything in focus, and fewer than 3 blades a circular aperture
+	Perspective {
+		aperture_radius: f32,
+		focus_distance: f32,
+		aperture_blades: u32,
+	},
+	// parallel rays from a `width` by `height` rectangle, in world units
+	Orthographic { width: f32, height: f32 },
+	// equidistant fisheye, `field_of_view` radians across the image circle
+	Fisheye { field_of_view: f32 },
+	// 360° by 180° panorama
+	Equirectangular,
+}
+
+impl CameraSettings {
+	/// Camera at the origin looking down -z, as set up here, for a CELLS_WIDE by CELLS_HIGH
+	/// image.
+	pub fn camera(&self) -> Camera {
+		Camera {
+			projection: self.projection_for(CELLS_WIDE, CELLS_HIGH),
+			stereo: self.stereo,
+			shutter_open: self.shutter_open,
+			shutter_close: self.shutter_close,
+			..Camera::default()
+		}
+	}
+
+	/// The projection set up here, for a `width` by `height` image. Pixels keep the shape
+	/// they have at CELLS_WIDE by CELLS_HIGH, so a wider image sees further to the sides.
+	pub fn projection_for(&self, width: usize, height: usize) -> Arc<dyn Projection> {
+		let aspect = width as f32 / height as f32;
+		let stretch = aspect / (CELLS_WIDE as f32 / CELLS_HIGH as f32);
+
+		match self.projection {
+			ProjectionKind::Perspective {
+				aperture_radius,
+				focus_distance,
+				aperture_blades,
+			} => {
+				let default = Perspective::default();
+				Arc::new(Perspective {
+					sensor_width: default.sensor_width * stretch,
+					aperture_radius,
+					focus_distance,
+					aperture_blades,
+					..default
+				})
+			}
+			ProjectionKind::Orthographic { width, height } => Arc::new(Orthographic {
+				width: width * stretch,
+				height,
+			}),
+			ProjectionKind::Fisheye { field_of_view } => Arc::new(Fisheye {
+				field_of_view,
+				aspect,
+			}),
+			ProjectionKind::Equirectangular => Arc::new(Equirectangular),
+		}
+	}
+}
+
+impl Camera {
+	/// Ray through `film` from the centre of the camera, ignoring any stereo setup.
+	/// `time` is a sample in [0, 1] spread over the shutter interval.
+	pub fn ray(&self, film: V2, lens: V2, time: f32) -> Option<Ray> {
+		self.eye_ray(film, lens, time, 0.0, 1.0)
+	}
+
+	/// Rays seen at `film`, each with the color filter to apply to what it sees.
+	/// Anaglyph stereo gives one ray per eye, other cameras fill only the first slot, and
+	/// points outside the projection give none. Fixed size, as this runs for every sample.
+	pub fn rays(&self, film: V2, lens: V2, time: f32) -> [Option<(Ray, Color)>; 2] {
+		let white = V3::new(1.0, 1.0, 1.0);
+		let stereo = match self.stereo {
+			None => return [self.ray(film, lens, time).map(|ray| (ray, white)), None],
+			Some(ref stereo) => stereo,
+		};
+
+		let left = -stereo.interocular_distance / 2.0;
+		let right = stereo.interocular_distance / 2.0;
+		let eye = |film: V2, offset: f32| {
+			self.eye_ray(film, lens, time, offset, stereo.convergence_distance)
+		};
+
+		match stereo.layout {
+			StereoLayout::SideBySide => {
+				let ray = if film.x < 0.5 {
+					eye(V2::new(film.x * 2.0, film.y), left)
+				} else {
+					eye(V2::new(film.x * 2.0 - 1.0, film.y), right)
+				};
+				[ray.map(|ray| (ray, white)), None]
+			}
+			StereoLayout::TopBottom => {
+				let ray = if film.y >= 0.5 {
+					eye(V2::new(film.x, film.y * 2.0 - 1.0), left)
+				} else {
+					eye(V2::new(film.x, film.y * 2.0), right)
+				};
+				[ray.map(|ray| (ray, white)), None]
+			}
+			StereoLayout::Anaglyph => [
+				eye(film, left).map(|ray| (ray, V3::new(1.0, 0.0, 0.0))),
+				eye(film, right).map(|ray| (ray, V3::new(0.0, 1.0, 1.0))),
+			],
+		}
+	}
+
+	// ray from an eye `offset` along the camera's x axis, aimed to cross the centre
+	// eye's ray `convergence` away so both views line up there
+	fn eye_ray(&self, film: V2, lens: V2, time: f32, offset: f32, convergence: f32) -> Option<Ray> {
+		let ray = self.projection.ray(film, lens)?;
+
+		let eye = V3::new(offset, 0.0, 0.0);
+		let converged = ray.origin + ray.direction.normalize() * convergence;
+
+		Some(Ray {
+			origin: self.position + self.orientation.rotate_vector(ray.origin + eye),
+			direction: self
+				.orientation
+				.rotate_vector(converged - ray.origin - eye)
+				.normalize(),
+			time: self.shutter_open + (self.shutter_close - self.shutter_open) * time,
+		})
+	}
+}
+
+/// Thin lens perspective projection.
+#[derive(Debug, Clone)]
+pub struct Perspective {
+	pub sensor_width: f32,
+	pub sensor_height: f32,
+	// distance from the lens to the sensor, which sets the field of view
+	pub sensor_distance: f32,
+	// 0 is a pinhole, with everything in focus
+	pub aperture_radius: f32,
+	// distance along the view direction that is perfectly in focus
+	pub focus_distance: f32,
+	// number of straight aperture edges, giving polygonal bokeh. Fewer than 3 is a circular aperture
+	pub aperture_blades: u32,
+}
+
+impl Default for Perspective {
+	fn default() -> Perspective {
+		Perspective {
+			sensor_width: 1.0,
+			sensor_height: 1.0,
+			sensor_distance: 0.5,
+			aperture_radius: 0.0,
+			focus_distance: 1.0,
+			aperture_blades: 0,
+		}
+	}
+}
+
+impl Projection for Perspective {
+	fn ray(&self, film: V2, lens: V2) -> Option<Ray> {
+		let sensor_point = V3 {
+			x: -self.sensor_width / 2.0 + film.x * self.sensor_width,
+			y: -self.sensor_height / 2.0 + film.y * self.sensor_height,
+			z: -self.sensor_distance,
+		};
+
+		if self.aperture_radius <= 0.0 {
+			return Some(Ray {
+				origin: V3::zero(),
+				direction: sensor_point,
+				time: 0.0,
+			});
+		}
+
+		// every ray through the lens converges on the focal plane
+		let focus_point = sensor_point * (self.focus_distance / self.sensor_distance);
+		let lens_point = sample_aperture(lens, self.aperture_blades) * self.aperture_radius;
+		let lens_point = V3::new(lens_point.x, lens_point.y, 0.0);
+
+		Some(Ray {
+			origin: lens_point,
+			direction: focus_point - lens_point,
+			time: 0.0,
+		})
+	}
+}
+
+/// Parallel rays from a `width` by `height` rectangle, for technical drawings.
+#[derive(Debug, Clone)]
+pub struct Orthographic {
+	pub width: f32,
+	pub height: f32,
+}
+
+impl Projection for Orthographic {
+	fn ray(&self, film: V2, _lens: V2) -> Option<Ray> {
+		Some(Ray {
+			origin: V3 {
+				x: (film.x - 0.5) * self.width,
+				y: (film.y - 0.5) * self.height,
+				z: 0.0,
+			},
+			direction: -V3::unit_z(),
+			time: 0.0,
+		})
+	}
+}
+
+/// Equidistant fisheye: distance from the image centre is proportional to the angle
+/// from the view direction. The image circle touches the top and bottom of the frame.
+#[derive(Debug, Clone)]
+pub struct Fisheye {
+	// full angle across the image circle, in radians
+	pub field_of_view: f32,
+	// image width / height, to keep the image circle round
+	pub aspect: f32,
+}
+
+impl Projection for Fisheye {
+	fn ray(&self, film: V2, _lens: V2) -> Option<Ray> {
+		let offset = V2 {
+			x: (film.x * 2.0 - 1.0) * self.aspect,
+			y: film.y * 2.0 - 1.0,
+		};
+		let radius = offset.magnitude();
+		if radius > 1.0 {
+			return None;
+		}
+
+		let theta = radius * self.field_of_view / 2.0;
+		let phi = offset.y.atan2(offset.x);
+
+		Some(Ray {
+			origin: V3::zero(),
+			direction: V3 {
+				x: theta.sin() * phi.cos(),
+				y: theta.sin() * phi.sin(),
+				z: -theta.cos(),
+			},
+			time: 0.0,
+		})
+	}
+}
+
+/// 360° by 180° latitude/longitude panorama, centred on the view direction.
+#[derive(Debug, Clone)]
+pub struct Equirectangular;
+
+impl Projection for Equirectangular {
+	fn ray(&self, film: V2, _lens: V2) -> Option<Ray> {
+		let longitude = (film.x * 2.0 - 1.0) * PI;
+		let latitude = (film.y - 0.5) * PI;
+
+		Some(Ray {
+			origin: V3::zero(),
+			direction: V3 {
+				x: longitude.sin() * latitude.cos(),
+				y: latitude.sin(),
+				z: -longitude.cos() * latitude.cos(),
+			},
+			time: 0.0,
+		})
+	}
+}
+
+// uniform point on the unit disk, or on a regular polygon inscribed in it
+fn sample_aperture(sample: V2, blades: u32) -> V2 {
+	if blades < 3 {
+		return concentric_disk(sample);
+	}
+
+	// pick a triangular wedge of the polygon, then a point inside it
+	let blades = blades as f32;
+	let scaled = sample.x * blades;
+	let wedge = scaled.floor().min(blades - 1.0);
+	let u = scaled - wedge;
+
+	let corner = |i: f32| {
+		let angle = 2.0 * PI * i / blades;
+		V2::new(angle.cos(), angle.sin())
+	};
+	let (a, b) = (corner(wedge), corner(wedge + 1.0));
+
+	let root = u.sqrt();
+	a * (root * (1.0 - sample.y)) + b * (root * sample.y)
+}
+
+// Shirley and Chiu's area preserving square to disk mapping
+fn concentric_disk(sample: V2) -> V2 {
+	let offset = sample * 2.0 - V2::new(1.0, 1.0);
+	if offset.x == 0.0 && offset.y == 0.0 {
+		return V2::zero();
+	}
+
+	let (radius, theta) = if offset.x.abs() > offset.y.abs() {
+		(offset.x, PI / 4.0 * (offset.y / offset.x))
+	} else {
+		(offset.y, PI / 2.0 - PI / 4.0 * (offset.x / offset.y))
+	};
+
+	V2::new(theta.cos(), theta.sin()) * radius
+}
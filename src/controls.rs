@@ -0,0 +1,121 @@
+use camera::{Camera, Projection};
+use cgmath::prelude::*;
+use cgmath::{Quaternion, Rad};
+use glium::glutin::{ElementState, MouseButton, VirtualKeyCode, WindowEvent};
+use std::f32::consts::PI;
+use std::sync::Arc;
+use types::*;
+
+// world units per second
+const MOVE_SPEED: f32 = 10.0;
+// radians per pixel of mouse movement
+const LOOK_SPEED: f32 = 0.003;
+
+/// WASD to fly, dragging with the left mouse button to look around.
+pub struct FlyControls {
+	camera: Camera,
+	// orientation the yaw and pitch are relative to
+	base_orientation: Quaternion<f32>,
+	yaw: f32,
+	pitch: f32,
+	forward: bool,
+	back: bool,
+	left: bool,
+	right: bool,
+	looking: bool,
+	cursor: Option<(f64, f64)>,
+	// turned since the last update
+	turned: bool,
+}
+
+impl FlyControls {
+	pub fn new(camera: Camera) -> FlyControls {
+		FlyControls {
+			base_orientation: camera.orientation,
+			camera,
+			yaw: 0.0,
+			pitch: 0.0,
+			forward: false,
+			back: false,
+			left: false,
+			right: false,
+			looking: false,
+			cursor: None,
+			turned: false,
+		}
+	}
+
+	pub fn handle_event(&mut self, event: &WindowEvent) {
+		match *event {
+			WindowEvent::KeyboardInput { input, .. } => {
+				let pressed = input.state == ElementState::Pressed;
+				match input.virtual_keycode {
+					Some(VirtualKeyCode::W) => self.forward = pressed,
+					Some(VirtualKeyCode::S) => self.back = pressed,
+					Some(VirtualKeyCode::A) => self.left = pressed,
+					Some(VirtualKeyCode::D) => self.right = pressed,
+					_ => {}
+				}
+			}
+			WindowEvent::MouseInput {
+				state,
+				button: MouseButton::Left,
+				..
+			} => {
+				self.looking = state == ElementState::Pressed;
+			}
+			WindowEvent::CursorMoved { position, .. } => {
+				if let Some((x, y)) = self.cursor.filter(|_| self.looking) {
+					// the window's y axis points down, the camera's up
+					self.yaw -= (position.0 - x) as f32 * LOOK_SPEED;
+					self.pitch -= (position.1 - y) as f32 * LOOK_SPEED;
+					self.pitch = self.pitch.clamp(-PI / 2.0 + 0.01, PI / 2.0 - 0.01);
+					self.turned = true;
+				}
+				self.cursor = Some(position);
+			}
+			WindowEvent::Focused(false) => {
+				// key releases are lost once the window is in the background
+				self.forward = false;
+				self.back = false;
+				self.left = false;
+				self.right = false;
+				self.looking = false;
+			}
+			_ => {}
+		}
+	}
+
+	/// Look through `projection` from now on, e.g. when the image changes shape. The new
+	/// camera.
+	pub fn set_projection(&mut self, projection: Arc<dyn Projection>) -> Camera {
+		self.camera.projection = projection;
+		self.camera.clone()
+	}
+
+	/// Move by the keys held over `seconds`. The new camera, if it moved or turned.
+	pub fn update(&mut self, seconds: f32) -> Option<Camera> {
+		let axis = |positive: bool, negative: bool| positive as i32 as f32 - negative as i32 as f32;
+		let forward = axis(self.forward, self.back);
+		let right = axis(self.right, self.left);
+
+		if forward == 0.0 && right == 0.0 && !self.turned {
+			return None;
+		}
+		self.turned = false;
+
+		self.camera.orientation = self.base_orientation
+			* Quaternion::from_angle_y(Rad(self.yaw))
+			* Quaternion::from_angle_x(Rad(self.pitch));
+
+		let direction = V3::new(right, 0.0, -forward);
+		if direction.magnitude2() > 0.0 {
+			self.camera.position += self
+				.camera
+				.orientation
+				.rotate_vector(direction.normalize() * MOVE_SPEED * seconds);
+		}
+
+		Some(self.camera.clone())
+	}
+}
@@ -0,0 +1,84 @@
+use filter::Filter;
+use types::*;
+use util::AtomicF32;
+
+/// Running weighted sums of the samples splatted onto each pixel through a
+/// reconstruction filter. Samples from any thread can land on any pixel.
+pub struct Film {
+	filter: Filter,
+	width: usize,
+	height: usize,
+	pixels: Vec<FilmPixel>,
+}
+
+#[derive(Default)]
+struct FilmPixel {
+	color: [AtomicF32; 3],
+	weight: AtomicF32,
+}
+
+impl Film {
+	/// Empty film of `width` by `height` pixels.
+	pub fn new(filter: Filter, width: usize, height: usize) -> Film {
+		Film {
+			filter,
+			width,
+			height,
+			pixels: (0..width * height)
+				.map(|_| FilmPixel::default())
+				.collect(),
+		}
+	}
+
+	/// Indices of the pixels a sample in pixel (x, y) can contribute to.
+	pub fn footprint(&self, x: usize, y: usize) -> impl Iterator<Item = usize> {
+		let reach = self.filter.radius().ceil() as usize;
+		let (x_min, x_max) = (x.saturating_sub(reach), (x + reach).min(self.width - 1));
+		let (y_min, y_max) = (y.saturating_sub(reach), (y + reach).min(self.height - 1));
+
+		let width = self.width;
+		(y_min..=y_max).flat_map(move |y| (x_min..=x_max).map(move |x| y * width + x))
+	}
+
+	/// Add a sample at `position`, in pixels from the corner of the image, to every
+	/// pixel whose filter covers it.
+	pub fn splat(&self, position: V2, color: Color) {
+		let radius = self.filter.radius();
+		// pixel centres are at half integers
+		let x_min = (position.x - 0.5 - radius).ceil().max(0.0) as usize;
+		let x_max = ((position.x - 0.5 + radius).floor() as isize).min(self.width as isize - 1);
+		let y_min = (position.y - 0.5 - radius).ceil().max(0.0) as usize;
+		let y_max = ((position.y - 0.5 + radius).floor() as isize).min(self.height as isize - 1);
+
+		for y in y_min as isize..=y_max {
+			for x in x_min as isize..=x_max {
+				let offset = V2::new(x as f32 + 0.5 - position.x, y as f32 + 0.5 - position.y);
+				let weight = self.filter.evaluate(offset);
+				if weight == 0.0 {
+					continue;
+				}
+
+				let pixel = &self.pixels[y as usize * self.width + x as usize];
+				pixel.color[0].add(color.x * weight);
+				pixel.color[1].add(color.y * weight);
+				pixel.color[2].add(color.z * weight);
+				pixel.weight.add(weight);
+			}
+		}
+	}
+
+	/// Filtered color of the pixel at `index`, from the samples splatted so far.
+	pub fn resolve(&self, index: usize) -> Color {
+		let pixel = &self.pixels[index];
+		let weight = pixel.weight.load();
+		if weight == 0.0 {
+			return V3::new(0.0, 0.0, 0.0);
+		}
+
+		V3::new(
+			pixel.color[0].load(),
+			pixel.color[1].load(),
+			pixel.color[2].load(),
+		) / weight
+	}
+}
@@ -0,0 +1,77 @@
+use std::f32::consts::PI;
+use types::*;
+
+/// Reconstruction filter weighting each sample's contribution to the pixels around it.
+/// Every filter is separable, the product of the same 1D curve in x and y.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum Filter {
+	// plain average of the samples within `radius` pixels
+	Box { radius: f32 },
+	// weight falling linearly to 0 at `radius`
+	Tent { radius: f32 },
+	// bell curve with falloff `alpha`, shifted down to reach 0 at `radius`
+	Gaussian { radius: f32, alpha: f32 },
+	// Mitchell-Netravali cubic, trading blur (`b`) against ringing (`c`)
+	Mitchell { radius: f32, b: f32, c: f32 },
+	// sinc of the distance in pixels, windowed by a sinc `tau` times as wide, so it dips
+	// negative past a pixel and sharpens. Usually `radius` is `tau`, cutting at the window's zero
+	Lanczos { radius: f32, tau: f32 },
+}
+
+impl Filter {
+	/// Distance in pixels, along each axis, beyond which the weight is 0.
+	pub fn radius(&self) -> f32 {
+		match *self {
+			Filter::Box { radius }
+			| Filter::Tent { radius }
+			| Filter::Gaussian { radius, .. }
+			| Filter::Mitchell { radius, .. }
+			| Filter::Lanczos { radius, .. } => radius,
+		}
+	}
+
+	/// Weight of a sample `offset` pixels away from a pixel centre. May be negative.
+	pub fn evaluate(&self, offset: V2) -> f32 {
+		self.evaluate_1d(offset.x) * self.evaluate_1d(offset.y)
+	}
+
+	fn evaluate_1d(&self, x: f32) -> f32 {
+		let x = x.abs();
+		if x > self.radius() {
+			return 0.0;
+		}
+
+		match *self {
+			Filter::Box { .. } => 1.0,
+			Filter::Tent { radius } => radius - x,
+			Filter::Gaussian { radius, alpha } => {
+				(-alpha * x * x).exp() - (-alpha * radius * radius).exp()
+			}
+			Filter::Mitchell { radius, b, c } => mitchell(2.0 * x / radius, b, c),
+			Filter::Lanczos { tau, .. } => sinc(x) * sinc(x / tau),
+		}
+	}
+}
+
+// the cubic over [0, 2]
+fn mitchell(x: f32, b: f32, c: f32) -> f32 {
+	if x > 1.0 {
+		((-b - 6.0 * c) * x * x * x
+			+ (6.0 * b + 30.0 * c) * x * x
+			+ (-12.0 * b - 48.0 * c) * x
+			+ (8.0 * b + 24.0 * c)) / 6.0
+	} else {
+		((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+			+ (-18.0 + 12.0 * b + 6.0 * c) * x * x
+			+ (6.0 - 2.0 * b)) / 6.0
+	}
+}
+
+fn sinc(x: f32) -> f32 {
+	if x.abs() < 1e-5 {
+		1.0
+	} else {
+		(PI * x).sin() / (PI * x)
+	}
+}
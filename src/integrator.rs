@@ -0,0 +1,336 @@
+use cgmath::prelude::*;
+use material::Bump;
+use sampler::Sampler;
+use scene::Scene;
+use shapes::*;
+use texture::Texture;
+use types::*;
+use util::{point_in_unit_sphere, V3Extensions};
+
+// Intersect a single object, passing through any parts cut away by its alpha mask.
+fn object_intersection(obj: &Object2, ray: &Ray, textures: &[Texture]) -> Option<HitRecord> {
+	let mut hit = obj.closest_intersection(ray)?;
+
+	if let Some((texture, threshold)) = obj.material.alpha_mask {
+		while textures[texture].alpha(hit.uv) < threshold {
+			let continued_ray = Ray {
+				origin: hit.point,
+				direction: ray.direction,
+				time: ray.time,
+			};
+			let t = hit.t;
+			hit = obj.closest_intersection(&continued_ray)?;
+			hit.t += t;
+		}
+	}
+
+	Some(hit)
+}
+
+fn closest_intersect<'a>(
+	ray: &Ray,
+	scene: &'a Scene,
+	textures: &[Texture],
+) -> Option<(HitRecord, &'a Object2)> {
+	let objects = scene.objects();
+
+	scene
+		.bvh()
+		.closest_hit(ray, |id| {
+			object_intersection(&objects[id], ray, textures).map(|mut hit| {
+				hit.object_id = id;
+				hit
+			})
+		})
+		.map(|hit| (hit, &objects[hit.object_id]))
+}
+
+fn trace_shadow(
+	point: V3,
+	time: f32,
+	light_position: V3,
+	scene: &Scene,
+	textures: &[Texture],
+) -> bool {
+	let shadow_ray = Ray {
+		origin: point,
+		direction: (light_position - point).normalize(),
+		time,
+	};
+
+	match closest_intersect(&shadow_ray, scene, textures) {
+		None => false,
+		// light may be closer than object
+		Some((hit, _obj)) => (light_position - point).magnitude() >= hit.t,
+	}
+}
+
+// offset in texture space used to take height map derivatives
+const BUMP_DELTA: f32 = 1.0 / 1024.0;
+
+// orthonormal tangent frame around the shading normal, keeping the handedness of the texture mapping
+fn tangent_frame(hit: &HitRecord) -> (V3, V3) {
+	let normal = hit.shading_normal;
+
+	let tangent = (hit.tangent - normal * normal.dot(hit.tangent)).normalize();
+	let bitangent = if normal.cross(tangent).dot(hit.bitangent) < 0.0 {
+		tangent.cross(normal)
+	} else {
+		normal.cross(tangent)
+	};
+
+	(tangent, bitangent)
+}
+
+fn bumped_normal(hit: &HitRecord, bump: Bump, textures: &[Texture]) -> V3 {
+	let normal = hit.shading_normal;
+
+	match bump {
+		Bump::None => normal,
+		Bump::NormalMap(texture) => {
+			let (tangent, bitangent) = tangent_frame(hit);
+			let texel = textures[texture].data(hit.uv, hit.object_point) * 2.0
+				- V3::new(1.0, 1.0, 1.0);
+
+			(tangent * texel.x + bitangent * texel.y + normal * texel.z).normalize()
+		}
+		Bump::HeightMap(texture, strength) => {
+			let (tangent, bitangent) = tangent_frame(hit);
+			let texture = &textures[texture];
+			let height = |du: f32, dv: f32| {
+				let texel = texture.data(
+					hit.uv + V2::new(du, dv),
+					hit.object_point + hit.object_tangent * du + hit.object_bitangent * dv,
+				);
+				(texel.x + texel.y + texel.z) / 3.0
+			};
+
+			let base = height(0.0, 0.0);
+			let slope_u = (height(BUMP_DELTA, 0.0) - base) / BUMP_DELTA;
+			let slope_v = (height(0.0, BUMP_DELTA) - base) / BUMP_DELTA;
+
+			(normal - (tangent * slope_u + bitangent * slope_v) * strength).normalize()
+		}
+	}
+}
+
+fn trace(
+	ray: &Ray,
+	scene: &Scene,
+	textures: &[Texture],
+	sampler: &mut Sampler,
+	depth: u32,
+) -> Color {
+	if depth > MAX_TRACE_DEPTH {
+		return BACKGROUND_COLOR;
+	}
+
+	match closest_intersect(ray, scene, textures) {
+		None => BACKGROUND_COLOR,
+		Some((hit, obj)) => {
+			let normal = bumped_normal(&hit, obj.material.bump, textures);
+			shade(ray, &hit, obj, normal, scene, textures, sampler, depth)
+		}
+	}
+}
+
+// trace a ray from the camera, also returning what it first hit for the viewer's
+// other display channels
+pub fn trace_primary(
+	ray: &Ray,
+	scene: &Scene,
+	textures: &[Texture],
+	sampler: &mut Sampler,
+) -> (Color, SurfaceChannels) {
+	match closest_intersect(ray, scene, textures) {
+		None => (BACKGROUND_COLOR, SurfaceChannels::default()),
+		Some((hit, obj)) => {
+			let normal = bumped_normal(&hit, obj.material.bump, textures);
+			let surface = SurfaceChannels {
+				normal,
+				depth: hit.t,
+				albedo: obj.material.diffuse.color(&hit, textures),
+			};
+			(
+				shade(ray, &hit, obj, normal, scene, textures, sampler, 0),
+				surface,
+			)
+		}
+	}
+}
+
+// color of the light leaving `hit` back along `ray`
+#[allow(clippy::too_many_arguments)]
+fn shade(
+	ray: &Ray,
+	hit: &HitRecord,
+	obj: &Object2,
+	normal: V3,
+	scene: &Scene,
+	textures: &[Texture],
+	sampler: &mut Sampler,
+	depth: u32,
+) -> Color {
+	let intersect = hit.point;
+
+	// light the side the ray hit, so the backs of open surfaces aren't left black
+	let normal = if hit.front_face { normal } else { -normal };
+
+	let material = &obj.material;
+
+	let total_brightness = scene
+		.lights()
+		.iter()
+		.map(|light| light.brightness)
+		.sum::<f32>();
+
+	// one sample on each light, shared by its diffuse and specular terms
+	let (diffuse_factor, specular_factor) = scene.lights().iter().fold(
+		(0.0, 0.0),
+		|(diffuse, specular), light| {
+			let light_position = light.sample_point(sampler.get_2d());
+			if trace_shadow(intersect, ray.time, light_position, scene, textures) {
+				return (diffuse, specular);
+			}
+
+			let light_vec = (light_position - intersect).normalize();
+			let reflected = (-light_vec).reflect(normal);
+			let rdotn = reflected.dot(normal);
+
+			let diffuse_term =
+				light_vec.dot(normal).max(0.0) * (light.brightness / total_brightness);
+			//* 2.73_f32.powf(0.00001 * (light.position - intersect).magnitude())
+
+			(
+				diffuse + diffuse_term,
+				specular + rdotn.max(0.0).powf(material.shininess),
+			)
+		},
+	);
+
+	let diffuse_color = material.diffuse.color(hit, textures);
+	let specular = material.specular.scalar(hit, textures);
+
+	let surface_color =
+		diffuse_factor * diffuse_color + specular_factor * specular * diffuse_color;
+
+	let reflectivity = material.reflectivity.scalar(hit, textures);
+
+	let surface_color = if reflectivity > 0.0 {
+		let roughness = material.roughness.scalar(hit, textures);
+
+		// scatter glossy reflections, without sending them below the surface
+		let mirror_direction = ray.direction.reflect(normal);
+		let scatter = point_in_unit_sphere(sampler.get_2d(), sampler.get_1d());
+		let scattered_direction = (mirror_direction + scatter * roughness).normalize();
+
+		let reflected_ray = Ray {
+			direction: if scattered_direction.dot(normal) > 0.0 {
+				scattered_direction
+			} else {
+				mirror_direction
+			},
+			origin: intersect,
+			time: ray.time,
+		};
+
+		let reflected_color = trace(&reflected_ray, scene, textures, sampler, depth + 1);
+
+		reflected_color * reflectivity + surface_color * (1.0 - reflectivity)
+	} else {
+		surface_color
+	};
+
+	let emission = material.emission.color(hit, textures);
+
+	0.05 * diffuse_color + surface_color + emission
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use camera::Camera;
+	use material::{Channel, Material};
+	use scene_graph::{Geometry, Node};
+	use std::sync::Arc;
+	use texture::perforated_image;
+
+	// a 2x2 perforated screen 5 in front of the origin, with a solid backdrop 10 away
+	fn masked_scene() -> (Scene, Vec<Texture>) {
+		let textures = vec![Texture::Image(perforated_image(
+			64,
+			2,
+			image::Rgba([255, 255, 255, 255]),
+		))];
+
+		let quad = |z: f32, material: Material| {
+			let corners = [
+				V3::new(-1.0, -1.0, z),
+				V3::new(1.0, -1.0, z),
+				V3::new(1.0, 1.0, z),
+				V3::new(-1.0, 1.0, z),
+			];
+			let uvs = [
+				V2::new(0.0, 0.0),
+				V2::new(1.0, 0.0),
+				V2::new(1.0, 1.0),
+				V2::new(0.0, 1.0),
+			];
+			[[0, 1, 2], [0, 2, 3]].iter().fold(Node::new("quad"), |node, &[a, b, c]| {
+				node.with_geometry(Geometry {
+					shape: Arc::new(Shape::Triangle(Triangle::new_with_uv(
+						[corners[a], corners[b], corners[c]],
+						[uvs[a], uvs[b], uvs[c]],
+					))),
+					material,
+				})
+			})
+		};
+
+		let root = Node::new("root")
+			.with_child(quad(
+				-5.0,
+				Material {
+					diffuse: Channel::textured(V3::new(1.0, 1.0, 1.0), 0),
+					alpha_mask: Some((0, 0.5)),
+					..Material::default()
+				},
+			))
+			.with_child(quad(-10.0, Material::default()));
+
+		(Scene::new(&root, Camera::default()), textures)
+	}
+
+	fn ray_towards(target: V3) -> Ray {
+		Ray {
+			origin: V3::zero(),
+			direction: target.normalize(),
+			time: 0.0,
+		}
+	}
+
+	#[test]
+	fn rays_pass_through_holes_in_alpha_masks() {
+		let (scene, textures) = masked_scene();
+
+		// the middle of a hole, and between the holes
+		let hole = V3::new(-0.5, -0.5, -5.0);
+		let solid = V3::new(0.05, 0.0, -5.0);
+
+		let (hit, _) = closest_intersect(&ray_towards(hole), &scene, &textures).unwrap();
+		assert!((hit.t - hole.magnitude() * 2.0).abs() < 0.01);
+
+		let (hit, _) = closest_intersect(&ray_towards(solid), &scene, &textures).unwrap();
+		assert!((hit.t - solid.magnitude()).abs() < 0.01);
+	}
+
+	#[test]
+	fn shadows_pass_through_holes_in_alpha_masks() {
+		let (scene, textures) = masked_scene();
+		let light = V3::zero();
+
+		// points on the backdrop, seen through a hole and behind the screen
+		assert!(!trace_shadow(V3::new(-1.0, -1.0, -9.99), 0.0, light, &scene, &textures));
+		assert!(trace_shadow(V3::new(0.1, 0.0, -9.99), 0.0, light, &scene, &textures));
+	}
+}
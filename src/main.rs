@@ -5,261 +5,202 @@ extern crate image;
 extern crate rand;
 extern crate rayon;
 
-use cgmath::prelude::*;
 use glium::index::PrimitiveType;
+use glium::glutin::VirtualKeyCode;
+use glium::texture::{MipmapsOption, UncompressedFloatFormat};
+use glium::uniforms::MagnifySamplerFilter;
 use glium::{glutin, Surface};
-use image::GenericImage;
-use image::{load_from_memory, DynamicImage};
-use rand::*;
-use std::cmp::Ordering;
 use std::iter::Iterator;
+use std::path::Path;
 use std::sync::Arc;
 use std::vec::Vec;
 
+mod bvh;
+mod camera;
+mod controls;
+mod film;
+mod filter;
+mod integrator;
+mod material;
+mod noise;
+mod output;
+mod progress;
+mod render;
+mod sampler;
 mod scene;
+mod scene_graph;
 mod shapes;
+mod texture;
+mod tiles;
 mod types;
 mod util;
+mod view;
 
 use scene::Scene;
-use shapes::*;
+use controls::FlyControls;
+use render::RenderJob;
+use texture::Texture;
+use tiles::{ActiveTiles, Tile};
 use types::*;
-use util::V3Extensions;
+use view::View;
 
-fn make_cells() -> Cells {
-    let mut v = Vec::with_capacity(CELLS_HIGH * CELLS_WIDE);
-    for _ in 0..(CELLS_HIGH * CELLS_WIDE) {
-        v.push(Cell::new(DEFAULT_COLOR));
+// a corner of the quad the image is drawn on, or the end of a tile outline
+mod vertex {
+    #![allow(deprecated)]
+
+    #[derive(Copy, Clone)]
+    pub struct Vertex {
+        pub position: [f32; 2],
     }
-    Cells { data: Arc::new(v) }
+
+    implement_vertex!(Vertex, position);
 }
 
-fn closest_intersect<'a>(ray: &Ray, scene: &'a Scene) -> Option<(f32, &'a Object2)> {
-    scene
-        .objects()
+use vertex::Vertex;
+
+// outlines of `tiles` of a `width` by `height` image as pairs of line ends, in normalised
+// device coordinates
+fn tile_outlines(tiles: &[Tile], width: usize, height: usize) -> Vec<Vertex> {
+    let corner = |x: usize, y: usize| Vertex {
+        position: [
+            x as f32 / width as f32 * 2.0 - 1.0,
+            y as f32 / height as f32 * 2.0 - 1.0,
+        ],
+    };
+
+    tiles
         .iter()
-        .filter_map(move |obj| match obj.closest_intersection(ray) {
-            None => None,
-            Some(t) => Some((t, obj)),
+        .flat_map(|tile| {
+            let (left, right) = (tile.x, tile.x + tile.width);
+            let (bottom, top) = (tile.y, tile.y + tile.height);
+            vec![
+                corner(left, bottom),
+                corner(right, bottom),
+                corner(right, bottom),
+                corner(right, top),
+                corner(right, top),
+                corner(left, top),
+                corner(left, top),
+                corner(left, bottom),
+            ]
         })
-        .min_by(|(t1, _obj1), (t2, _obj2)| t1.partial_cmp(t2).unwrap_or(Ordering::Equal))
+        .collect()
 }
 
-fn trace_shadow(point: V3, light: &Light, scene: &Scene) -> bool {
-    let shadow_ray = Ray {
-        origin: point,
-        direction: (light.position - point).normalize(),
-    };
-    let closest_intersect = closest_intersect(&shadow_ray, &scene);
-
-    match closest_intersect {
-        None => false,
-        Some((t, _obj)) => {
-            // light may be closer than object
-            if (light.position - point).magnitude() < t {
-                false
-            } else {
-                true
-            }
-        }
+// copy the blocks of `channel` that changed since the last frame into `texture`. Depth goes
+// up raw, as its shading depends on the nearest surface anywhere and is done in the shader
+fn upload_changes(texture: &glium::texture::Texture2d, cells: &Cells, channel: DisplayChannel) {
+    for block in cells.take_dirty() {
+        let image = glium::texture::RawImage2d::from_raw_rgb(
+            cells.region_to_vec(&block, channel),
+            (block.width as u32, block.height as u32),
+        );
+        texture.write(
+            glium::Rect {
+                left: block.x as u32,
+                bottom: block.y as u32,
+                width: block.width as u32,
+                height: block.height as u32,
+            },
+            image,
+        );
     }
 }
 
-fn trace(ray: &Ray, scene: &Scene, textures: &Vec<DynamicImage>, depth: u32) -> Color {
-    if depth > MAX_TRACE_DEPTH {
-        return BACKGROUND_COLOR;
-    }
-
-    let closest_intersect = closest_intersect(&ray, &scene);
-
-    match closest_intersect {
-        None => BACKGROUND_COLOR,
-        Some((t, obj)) => {
-            let intersect = ray.direction * t;
-
-            let normal = obj.normal(intersect);
-
-            let total_brightness = scene
-                .lights()
-                .iter()
-                .map(|light| light.brightness)
-                .sum::<f32>();
-
-            let diffuse_factor: f32 = scene
-                .lights()
-                .iter()
-                .map(|light| -> f32 {
-                    let light_vec = (light.position - intersect).normalize();
-                    if trace_shadow(intersect, light, scene) {
-                        0.0
-                    } else {
-                        light_vec.dot(normal).max(0.0) * (light.brightness / total_brightness)
-                        //* 2.73_f32.powf(0.00001 * (light.position - intersect).magnitude())
-                    }
-                })
-                .sum::<f32>();
-
-            let specular_factor: f32 = scene
-                .lights()
-                .iter()
-                .map(|light| -> f32 {
-                    let light_vec = (light.position - intersect).normalize();
-                    let reflected = (-light_vec).reflect(normal);
-                    let rdotn = reflected.dot(normal);
-                    let shininess = obj.shininess;
-                    if trace_shadow(intersect, light, scene) {
-                        0.0
-                    } else {
-                        rdotn.max(0.0).powf(shininess)
-                    }
-                })
-                .sum::<f32>();
-
-            let surface_color = diffuse_factor * obj.color + specular_factor * obj.color;
-
-            let surface_color = match obj.surface {
-                shapes::Surface::Diffuse => surface_color,
-                shapes::Surface::Reflective(portion) => {
-                    let reflected_ray = Ray {
-                        direction: ray.direction.reflect(normal),
-                        origin: intersect,
-                    };
-
-                    let reflected_color = trace(&reflected_ray, scene, textures, depth + 1);
-
-                    reflected_color * portion + surface_color * (1.0 - portion)
-                }
-                shapes::Surface::Textured(texture) => {
-                    let texture_coord = obj.get_texture_coord(intersect);
-
-                    let texture = &textures[texture];
-                    let (width, height) = texture.dimensions();
-                    //println!("width: {} height: {}", width, height);
-
-                    let pixel_x = (width as f32 * texture_coord.x).floor() as u32;
-                    let pixel_y = (height as f32 * texture_coord.y).floor() as u32;
-
-                    //println!("x: {} y: {}", pixel_x, pixel_y);
-                    let pixel = texture.get_pixel(pixel_x, pixel_y);
-
-                    //println!("{:?}", pixel);
-
-                    let color = V3 {
-                        x: pixel.data[0] as f32 / 256.0,
-                        y: pixel.data[1] as f32 / 256.0,
-                        z: pixel.data[2] as f32 / 256.0,
-                    };
-
-                    V3 {
-                        x: color.x.powf(2.0) * surface_color.x,
-                        y: color.y.powf(2.0) * surface_color.y,
-                        z: color.z.powf(2.0) * surface_color.z,
-                    }
-                }
-            };
-
-            0.05 * obj.color + surface_color
-        }
+// write `channel` of the image to a PNG, exposed as on screen, and every channel at full
+// range to an EXR, and the image to a Radiance HDR, all named for the current time
+fn save_images(cells: &Cells, channel: DisplayChannel, exposure: f32) {
+    let name = output::timestamp();
+
+    let png = format!("{}.png", name);
+    let exr = format!("{}.exr", name);
+    let hdr = format!("{}.hdr", name);
+    let (width, height) = (cells.width(), cells.height());
+    let shown = cells.to_display_vec(channel);
+    let channels = output::cell_channels(cells, EXR_COLOR_PIXEL_TYPE);
+    let beauty = cells.to_vec(DisplayChannel::Beauty);
+    let saved = output::save_png(Path::new(&png), width, height, &shown, exposure)
+        .and_then(|_| output::save_exr(Path::new(&exr), width, height, &channels))
+        .and_then(|_| output::save_hdr(Path::new(&hdr), width, height, &beauty));
+    match saved {
+        Ok(()) => eprintln!("saved {}, {} and {}", png, exr, hdr),
+        Err(error) => eprintln!("couldn't save {}: {}", name, error),
     }
 }
 
-fn get_xy(index: usize) -> (usize, usize) {
-    (index % CELLS_WIDE, index / CELLS_WIDE)
+// floating point, so values over 1 survive to be brought down by the exposure. Kept until the
+// render changes size, with only the parts of the image that changed written each frame
+fn empty_cells_texture(
+    display: &glium::Display,
+    width: usize,
+    height: usize,
+) -> glium::texture::Texture2d {
+    glium::texture::Texture2d::empty_with_format(
+        display,
+        UncompressedFloatFormat::F32F32F32,
+        MipmapsOption::NoMipmap,
+        width as u32,
+        height as u32,
+    ).unwrap()
 }
 
-fn trace_rays(cells: Cells, textures: &Vec<DynamicImage>, scene: Scene) {
-    use rayon::prelude::*;
-
-    let mut range: Vec<usize> = (0..(CELLS_HIGH * CELLS_WIDE)).collect();
-    let range = range.as_mut_slice();
-    //thread_rng().shuffle(range);
-
-    let y_rotation = cgmath::Quaternion::from_arc(
-        V3 {
-            x: 0.0,
-            y: 0.0,
-            z: -1.0,
-        },
-        V3 {
-            x: 0.0,
-            y: 2.0,
-            z: -1.0,
-        },
-        None,
+// render without a window, reporting progress on stderr, then save the image
+fn render_headless(scene: &Arc<Scene>, textures: &Arc<Vec<Texture>>) {
+    let cells = Cells::new(CELLS_WIDE, CELLS_HIGH, DEFAULT_COLOR);
+    let mut render = RenderJob::start(
+        &cells,
+        &ActiveTiles::default(),
+        scene.camera().clone(),
+        scene,
+        textures,
     );
 
-    range.into_par_iter().for_each(|index| {
-        let (camera_sensor_width, camera_sensor_height, camera_sensor_dist) = (1.0, 1.0, 0.5);
-
-        let (cell_x, cell_y) = get_xy(*index);
-        let (cell_x, cell_y) = (cell_x as f32, cell_y as f32);
-
-        let mut colors = [V3::zero(); ANTIALIASING_DIV * ANTIALIASING_DIV];
-
-        let antialiasing_div_size = 1.0 / (ANTIALIASING_DIV as f32);
-
-        for x in 0..ANTIALIASING_DIV {
-            for y in 0..ANTIALIASING_DIV {
-                let x_offset = x as f32 * antialiasing_div_size + antialiasing_div_size / 2.0;
-                let y_offset = y as f32 * antialiasing_div_size + antialiasing_div_size / 2.0;
-
-                let ray_direction = V3 {
-                    x: -camera_sensor_width / 2.0
-                        + (cell_x + x_offset) * (camera_sensor_width / CELLS_WIDE as f32),
-                    y: -camera_sensor_height / 2.0
-                        + (cell_y + y_offset) * (camera_sensor_height / CELLS_HIGH as f32),
-                    z: -camera_sensor_dist,
-                };
-
-                //let ray_direction = y_rotation.rotate_vector(ray_direction);
+    while !render.is_finished() {
+        eprint!("\r{}    ", render.progress());
+        std::thread::sleep(std::time::Duration::from_secs(1));
+    }
+    render.wait();
+    eprintln!("\r{}    ", render.progress());
 
-                let mut ray = Ray {
-                    origin: V3 {
-                        x: 0.0,
-                        y: 0.0,
-                        z: 0.0,
-                    },
-                    direction: ray_direction,
-                };
-                ray.direction = ray.direction.normalize();
-                colors[x * ANTIALIASING_DIV + y] = trace(&ray, &scene, textures, 0);
-            }
-        }
+    save_images(&cells, DisplayChannel::Beauty, 0.0);
+}
 
-        let color = colors.iter().sum::<V3>() / colors.len() as f32;
+// shortest time between restarts of the render while the camera moves
+const RESTART_INTERVAL: std::time::Duration = std::time::Duration::from_millis(200);
 
-        let cell = &cells.data[*index];
+fn main() {
+    let mut textures = vec![];
+    let scene = Arc::new(Scene::initialise(&mut textures));
+    let textures = Arc::new(textures);
 
-        cell.set_content(color)
-    });
-}
+    if std::env::args().any(|arg| arg == "--headless") {
+        render_headless(&scene, &textures);
+        return;
+    }
 
-fn main() {
     // building the display, ie. the main object
     let mut events_loop = glutin::EventsLoop::new();
     let window = glutin::WindowBuilder::new();
     let context = glutin::ContextBuilder::new().with_vsync(true);
     let display = glium::Display::new(window, context, &events_loop).unwrap();
 
-    let cells = make_cells();
-
-    let thread2_cells = cells.clone();
-    std::thread::spawn(move || {
-        let mut textures = vec![];
-        let scene = Scene::initialise(&mut textures);
+    // the size of the render, which follows the window's if RENDER_AT_WINDOW_SIZE is set
+    let (width, height) = if RENDER_AT_WINDOW_SIZE {
+        let (width, height) = display.get_framebuffer_dimensions();
+        (width as usize, height as usize)
+    } else {
+        (CELLS_WIDE, CELLS_HIGH)
+    };
+    let mut cells = Cells::new(width, height, DEFAULT_COLOR);
+    let active_tiles = ActiveTiles::default();
 
-        trace_rays(thread2_cells, &textures, scene);
-    });
+    let mut controls = FlyControls::new(scene.camera().clone());
+    let camera = controls.set_projection(CAMERA.projection_for(width, height));
+    let mut view = View::new(width, height);
+    let mut render = RenderJob::start(&cells, &active_tiles, camera, &scene, &textures);
 
     // building the vertex buffer, which contains all the vertices that we will draw
     let vertex_buffer = {
-        #[derive(Copy, Clone)]
-        struct Vertex {
-            position: [f32; 2],
-        }
-
-        implement_vertex!(Vertex, position);
-
         glium::VertexBuffer::new(
             &display,
             &[
@@ -281,7 +222,7 @@ fn main() {
 
     // building the index buffer
     let index_buffer =
-        glium::IndexBuffer::new(&display, PrimitiveType::TriangleStrip, &[1 as u16, 2, 0, 3])
+        glium::IndexBuffer::new(&display, PrimitiveType::TriangleStrip, &[1u16, 2, 0, 3])
             .unwrap();
 
     // compiling shaders and linking them together
@@ -292,42 +233,172 @@ fn main() {
         },
     ).unwrap();
 
+    let tile_program = program!(&display,
+        140 => {
+            vertex: include_str!("shaders/tile.vert.glsl"),
+            fragment: include_str!("shaders/tile.frag.glsl"),
+        },
+    ).unwrap();
+
+    let mut cells_texture = empty_cells_texture(&display, width, height);
+
+    // what the viewer shows, and how much brighter or darker than rendered, in stops
+    let mut channel = DisplayChannel::Beauty;
+    let mut exposure = 0.0f32;
+    let mut uploaded_channel = channel;
+
     // the main loop
     let mut jessica = false;
+    let mut last_frame = std::time::Instant::now();
+    let mut last_title = last_frame;
+    // where the camera has moved to, and the size the window changed to, since the render
+    // last restarted
+    let mut moved_camera = None;
+    let mut resized_window = None;
+    let mut last_restart = last_frame;
     while !jessica {
-        let frame_deadline =
-            std::time::Instant::now() + std::time::Duration::from_millis(1_000 / 60);
+        let frame_start = std::time::Instant::now();
+        let frame_deadline = frame_start + std::time::Duration::from_millis(1_000 / 60);
+
+        // show progress in the title a few times a second
+        if frame_start - last_title >= std::time::Duration::from_millis(250) {
+            last_title = frame_start;
+            let status = if render.is_finished() {
+                "done"
+            } else if render.is_paused() {
+                "paused"
+            } else {
+                "rendering"
+            };
+            display
+                .gl_window()
+                .set_title(&format!("pstet - {} - {}", status, render.progress()));
+        }
+
+        if channel != uploaded_channel {
+            cells.mark_all_dirty();
+            uploaded_channel = channel;
+        }
+        upload_changes(&cells_texture, &cells, channel);
 
-        let cells_image = glium::texture::RawImage2d::from_raw_rgb(
-            cells.clone().to_vec(),
-            (CELLS_WIDE as u32, CELLS_HIGH as u32),
-        );
-        let cells_texture = glium::texture::Texture2d::new(&display, cells_image).unwrap();
         // drawing a frame
+        let nearest_depth = cells.nearest_depth();
         let mut target = display.draw();
-        target.clear_color(0.0, 1.0, 0.0, 0.0);
+        view.set_window_size(target.get_dimensions());
+        target.clear_color(0.0, 0.0, 0.0, 0.0);
         target
             .draw(
                 &vertex_buffer,
                 &index_buffer,
                 &program,
                 &uniform! {
-                    divisions: [CELLS_WIDE as f32, CELLS_HIGH as f32],
-                    cells: &cells_texture,
+                    divisions: [cells.width() as f32, cells.height() as f32],
+                    // sharp pixels when zoomed in
+                    cells: cells_texture
+                        .sampled()
+                        .magnify_filter(MagnifySamplerFilter::Nearest),
+                    exposure: exposure,
+                    depth: channel == DisplayChannel::Depth,
+                    // none yet, e.g. just after a restart, shades everything black
+                    nearest_depth: if nearest_depth.is_finite() { nearest_depth } else { 0.0 },
+                    view: view.transform(),
                 },
                 &Default::default(),
             )
             .unwrap();
+
+        // highlight the tiles being rendered
+        let outlines = tile_outlines(&active_tiles.to_vec(), cells.width(), cells.height());
+        if !outlines.is_empty() {
+            let outline_buffer = glium::VertexBuffer::new(&display, &outlines).unwrap();
+            target
+                .draw(
+                    &outline_buffer,
+                    glium::index::NoIndices(PrimitiveType::LinesList),
+                    &tile_program,
+                    &uniform! {
+                        color: [1.0f32, 1.0, 1.0, 1.0],
+                        view: view.transform(),
+                    },
+                    &Default::default(),
+                )
+                .unwrap();
+        }
         target.finish().unwrap();
 
         // polling and handling the events received by the window
-        events_loop.poll_events(|event| match event {
-            glutin::Event::WindowEvent { event, .. } => match event {
-                glutin::WindowEvent::Closed => jessica = true,
-                _ => (),
-            },
-            _ => (),
+        events_loop.poll_events(|event| {
+            if let glutin::Event::WindowEvent { event, .. } = event {
+                match event {
+                    glutin::WindowEvent::Closed => jessica = true,
+                    // nothing to render while the window is minimised
+                    glutin::WindowEvent::Resized(width, height)
+                        if RENDER_AT_WINDOW_SIZE && width > 0 && height > 0 =>
+                    {
+                        resized_window = Some((width as usize, height as usize))
+                    }
+                    glutin::WindowEvent::KeyboardInput {
+                        input:
+                            glutin::KeyboardInput {
+                                state: glutin::ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    } => match key {
+                        // space pauses and resumes the render
+                        VirtualKeyCode::Space => if render.is_paused() {
+                            render.resume()
+                        } else {
+                            render.pause()
+                        },
+                        // P saves what is on screen
+                        VirtualKeyCode::P => save_images(&cells, channel, exposure),
+                        // 1 to 4 pick the channel shown
+                        VirtualKeyCode::Key1 => channel = DisplayChannel::Beauty,
+                        VirtualKeyCode::Key2 => channel = DisplayChannel::Normals,
+                        VirtualKeyCode::Key3 => channel = DisplayChannel::Depth,
+                        VirtualKeyCode::Key4 => channel = DisplayChannel::Albedo,
+                        // + and - change the exposure by half a stop
+                        VirtualKeyCode::Equals | VirtualKeyCode::Add => exposure += 0.5,
+                        VirtualKeyCode::Minus | VirtualKeyCode::Subtract => exposure -= 0.5,
+                        _ => {}
+                    },
+                    _ => {}
+                }
+                controls.handle_event(&event);
+                view.handle_event(&event);
+            }
         });
+
+        // start again from a quick preview when the camera moves or the window changes size,
+        // but no more often than RESTART_INTERVAL so the preview has a chance to appear while
+        // it keeps changing
+        let elapsed = frame_start - last_frame;
+        last_frame = frame_start;
+        let seconds = elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 * 1e-9;
+        if let Some(camera) = controls.update(seconds) {
+            moved_camera = Some(camera);
+        }
+        if frame_start - last_restart >= RESTART_INTERVAL {
+            if let Some((width, height)) = resized_window.take() {
+                if (width, height) != (cells.width(), cells.height()) {
+                    render.cancel();
+                    cells = Cells::new(width, height, DEFAULT_COLOR);
+                    cells_texture = empty_cells_texture(&display, width, height);
+                    view.set_image_size(width, height);
+                    let camera = controls.set_projection(CAMERA.projection_for(width, height));
+                    moved_camera = None;
+                    render = RenderJob::start(&cells, &active_tiles, camera, &scene, &textures);
+                    last_restart = frame_start;
+                }
+            }
+            if let Some(camera) = moved_camera.take() {
+                render.restart(camera);
+                last_restart = frame_start;
+            }
+        }
+
         let time = std::time::Instant::now();
         if time < frame_deadline {
             std::thread::sleep(frame_deadline - time);
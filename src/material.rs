@@ -0,0 +1,96 @@
+use texture::Texture;
+use types::*;
+
+/// A material property: a constant, optionally multiplied by a texture looked up at the hit.
+#[derive(Debug, Clone, Copy)]
+pub struct Channel<T> {
+	pub value: T,
+	// Texture ID
+	pub texture: Option<usize>,
+}
+
+impl<T> Channel<T> {
+	pub fn constant(value: T) -> Channel<T> {
+		Channel {
+			value,
+			texture: None,
+		}
+	}
+
+	pub fn textured(value: T, texture: usize) -> Channel<T> {
+		Channel {
+			value,
+			texture: Some(texture),
+		}
+	}
+}
+
+impl Channel<Color> {
+	pub fn color(&self, hit: &HitRecord, textures: &[Texture]) -> Color {
+		match self.texture {
+			None => self.value,
+			Some(texture) => {
+				let texel = textures[texture].value(hit.uv, hit.object_point);
+				V3 {
+					x: texel.x * self.value.x,
+					y: texel.y * self.value.y,
+					z: texel.z * self.value.z,
+				}
+			}
+		}
+	}
+}
+
+impl Channel<f32> {
+	// scalar textures use the average of the colour channels
+	pub fn scalar(&self, hit: &HitRecord, textures: &[Texture]) -> f32 {
+		match self.texture {
+			None => self.value,
+			Some(texture) => {
+				let texel = textures[texture].value(hit.uv, hit.object_point);
+				self.value * (texel.x + texel.y + texel.z) / 3.0
+			}
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Bump {
+	None,
+	// Texture ID of a tangent space normal map
+	NormalMap(usize),
+	// Texture ID of a greyscale height map, and how strongly it raises the surface
+	HeightMap(usize, f32),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Material {
+	pub diffuse: Channel<Color>,
+	// scales the specular highlight
+	pub specular: Channel<f32>,
+	pub shininess: f32,
+	// portion of light reflected vs. diffuse
+	pub reflectivity: Channel<f32>,
+	// how far reflections are scattered from the mirror direction, 0 is a perfect mirror
+	pub roughness: Channel<f32>,
+	// light given off by the surface itself, regardless of lighting
+	pub emission: Channel<Color>,
+	pub bump: Bump,
+	// Texture ID whose alpha channel cuts holes in the surface, and the alpha below which it is cut
+	pub alpha_mask: Option<(usize, f32)>,
+}
+
+impl Default for Material {
+	fn default() -> Material {
+		Material {
+			diffuse: Channel::constant(V3::new(1.0, 1.0, 1.0)),
+			specular: Channel::constant(1.0),
+			shininess: 20.0,
+			reflectivity: Channel::constant(0.0),
+			roughness: Channel::constant(0.0),
+			emission: Channel::constant(V3::new(0.0, 0.0, 0.0)),
+			bump: Bump::None,
+			alpha_mask: None,
+		}
+	}
+}
@@ -0,0 +1,107 @@
+use types::*;
+
+// Ken Perlin's reference permutation
+const PERMUTATION: [u8; 256] = [
+	151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
+	142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
+	203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+	74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
+	220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
+	132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
+	186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
+	59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
+	70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+	178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
+	241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
+	176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
+	128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+fn hash(i: i32) -> usize {
+	PERMUTATION[(i & 255) as usize] as usize
+}
+
+fn fade(t: f32) -> f32 {
+	t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+	a + t * (b - a)
+}
+
+fn grad(hash: usize, x: f32, y: f32, z: f32) -> f32 {
+	// pick one of 12 gradient directions from the low 4 bits of the hash
+	let h = hash & 15;
+	let u = if h < 8 { x } else { y };
+	let v = if h < 4 {
+		y
+	} else if h == 12 || h == 14 {
+		x
+	} else {
+		z
+	};
+	(if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+/// Improved Perlin gradient noise, roughly in [-1, 1].
+pub fn perlin(point: V3) -> f32 {
+	let (cell_x, cell_y, cell_z) = (point.x.floor(), point.y.floor(), point.z.floor());
+	let (x, y, z) = (point.x - cell_x, point.y - cell_y, point.z - cell_z);
+	let (cell_x, cell_y, cell_z) = (cell_x as i32, cell_y as i32, cell_z as i32);
+
+	let (u, v, w) = (fade(x), fade(y), fade(z));
+
+	// hash the coordinates of the 8 cube corners
+	let a = hash(cell_x) as i32 + cell_y;
+	let aa = hash(a) as i32 + cell_z;
+	let ab = hash(a + 1) as i32 + cell_z;
+	let b = hash(cell_x + 1) as i32 + cell_y;
+	let ba = hash(b) as i32 + cell_z;
+	let bb = hash(b + 1) as i32 + cell_z;
+
+	lerp(
+		w,
+		lerp(
+			v,
+			lerp(u, grad(hash(aa), x, y, z), grad(hash(ba), x - 1.0, y, z)),
+			lerp(
+				u,
+				grad(hash(ab), x, y - 1.0, z),
+				grad(hash(bb), x - 1.0, y - 1.0, z),
+			),
+		),
+		lerp(
+			v,
+			lerp(
+				u,
+				grad(hash(aa + 1), x, y, z - 1.0),
+				grad(hash(ba + 1), x - 1.0, y, z - 1.0),
+			),
+			lerp(
+				u,
+				grad(hash(ab + 1), x, y - 1.0, z - 1.0),
+				grad(hash(bb + 1), x - 1.0, y - 1.0, z - 1.0),
+			),
+		),
+	)
+}
+
+/// Fractal Brownian motion: octaves of noise at doubling frequency and halving amplitude.
+pub fn fbm(point: V3, octaves: u32) -> f32 {
+	(0..octaves)
+		.map(|octave| {
+			let frequency = (1 << octave) as f32;
+			perlin(point * frequency) / frequency
+		})
+		.sum()
+}
+
+/// Like `fbm`, but summing the absolute value of each octave, which gives sharp creases.
+pub fn turbulence(point: V3, octaves: u32) -> f32 {
+	(0..octaves)
+		.map(|octave| {
+			let frequency = (1 << octave) as f32;
+			perlin(point * frequency).abs() / frequency
+		})
+		.sum()
+}
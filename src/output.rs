@@ -0,0 +1,581 @@
+use image;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use types::*;
+
+/// Name for saved images, from the current UTC time, e.g. "render-2026-10-19-153012".
+pub fn timestamp() -> String {
+	let seconds = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|duration| duration.as_secs())
+		.unwrap_or(0);
+	let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+	let time_of_day = seconds % 86_400;
+
+	format!(
+		"render-{:04}-{:02}-{:02}-{:02}{:02}{:02}",
+		year,
+		month,
+		day,
+		time_of_day / 3600,
+		time_of_day / 60 % 60,
+		time_of_day % 60
+	)
+}
+
+// year, month and day of the `days`th day since 1970-01-01, after Howard Hinnant's
+// "chrono-Compatible Low-Level Date Algorithms"
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+	let days = days + 719_468;
+	let era = days.div_euclid(146_097);
+	let day_of_era = days - era * 146_097;
+	let year_of_era =
+		(day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	// months counted from March, so the leap day comes last
+	let month_index = (5 * day_of_year + 2) / 153;
+	let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+	let month = if month_index < 10 { month_index + 3 } else { month_index - 9 } as u32;
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+	(year, month, day)
+}
+
+/// Write `pixels`, RGB rows from the bottom as given by `Cells::to_vec`, to a `width` by
+/// `height` 8 bit PNG, scaled by `exposure` stops the way the viewer shows them.
+pub fn save_png(
+	path: &Path,
+	width: usize,
+	height: usize,
+	pixels: &[f32],
+	exposure: f32,
+) -> io::Result<()> {
+	let scale = exposure.exp2();
+	let bytes: Vec<u8> = pixels
+		.chunks(width * 3)
+		.rev()
+		.flat_map(|row| row.iter())
+		.map(|&value| ((value * scale).clamp(0.0, 1.0) * 255.0).round() as u8)
+		.collect();
+
+	image::save_buffer(path, &bytes, width as u32, height as u32, image::RGB(8))
+}
+
+/// Precision of a channel in an OpenEXR file.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExrPixelType {
+	// 16 bit, half the size and enough for color, but not for depth
+	Half,
+	// 32 bit
+	Float,
+}
+
+/// One named channel of an OpenEXR file, a value per pixel, rows from the bottom.
+pub struct ExrChannel {
+	pub name: String,
+	pub pixel_type: ExrPixelType,
+	pub values: Vec<f32>,
+}
+
+impl ExrChannel {
+	/// Split `pixels`, RGB rows from the bottom as given by `Cells::to_vec`, into R, G and
+	/// B channels of `pixel_type`, named e.g. "albedo.R" when given a `layer`.
+	pub fn rgb(layer: Option<&str>, pixels: &[f32], pixel_type: ExrPixelType) -> Vec<ExrChannel> {
+		let prefix = layer.map(|layer| format!("{}.", layer)).unwrap_or_default();
+		["R", "G", "B"]
+			.iter()
+			.enumerate()
+			.map(|(component, name)| ExrChannel {
+				name: format!("{}{}", prefix, name),
+				pixel_type,
+				values: pixels.iter().skip(component).step_by(3).cloned().collect(),
+			})
+			.collect()
+	}
+}
+
+/// Every channel of `cells` at full range: the beauty image as R, G and B, the albedo
+/// layer, the normals as N.X, N.Y and N.Z, all of `pixel_type`, and the distance to the
+/// first surface as Z, 0 where the rays missed everything. Z is always 32 bit, as half
+/// floats would band distant surfaces.
+pub fn cell_channels(cells: &Cells, pixel_type: ExrPixelType) -> Vec<ExrChannel> {
+	let surfaces: Vec<SurfaceChannels> = cells.data.iter().map(|cell| cell.get_surface()).collect();
+	let normal = |name: &str, component: fn(&V3) -> f32| ExrChannel {
+		name: format!("N.{}", name),
+		pixel_type,
+		values: surfaces.iter().map(|surface| component(&surface.normal)).collect(),
+	};
+
+	let mut channels = ExrChannel::rgb(None, &cells.to_vec(DisplayChannel::Beauty), pixel_type);
+	channels.extend(ExrChannel::rgb(
+		Some("albedo"),
+		&cells.to_vec(DisplayChannel::Albedo),
+		pixel_type,
+	));
+	channels.push(normal("X", |normal| normal.x));
+	channels.push(normal("Y", |normal| normal.y));
+	channels.push(normal("Z", |normal| normal.z));
+	channels.push(ExrChannel {
+		name: "Z".to_string(),
+		pixel_type: ExrPixelType::Float,
+		values: surfaces.iter().map(|surface| surface.depth).collect(),
+	});
+	channels
+}
+
+/// Write `channels` of a `width` by `height` image to an uncompressed OpenEXR file, keeping
+/// the full range of the render.
+pub fn save_exr(path: &Path, width: usize, height: usize, channels: &[ExrChannel]) -> io::Result<()> {
+	// channels are stored in alphabetical order
+	let mut channels: Vec<&ExrChannel> = channels.iter().collect();
+	channels.sort_by(|a, b| a.name.cmp(&b.name));
+
+	// magic number, then version 2 for a single part scanline image
+	let mut header = vec![0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0];
+
+	// each channel as name, pixel type, linear flag, three reserved bytes and x and y sampling
+	let mut channel_list = vec![];
+	for channel in &channels {
+		channel_list.extend_from_slice(channel.name.as_bytes());
+		channel_list.push(0);
+		let type_code: i32 = match channel.pixel_type {
+			ExrPixelType::Half => 1,
+			ExrPixelType::Float => 2,
+		};
+		channel_list.extend_from_slice(&type_code.to_le_bytes());
+		channel_list.extend_from_slice(&[0, 0, 0, 0]);
+		channel_list.extend_from_slice(&1i32.to_le_bytes());
+		channel_list.extend_from_slice(&1i32.to_le_bytes());
+	}
+	channel_list.push(0);
+	add_attribute(&mut header, "channels", "chlist", &channel_list);
+
+	// no compression
+	add_attribute(&mut header, "compression", "compression", &[0]);
+
+	let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+		.iter()
+		.flat_map(|value| value.to_le_bytes().to_vec())
+		.collect();
+	add_attribute(&mut header, "dataWindow", "box2i", &window);
+	add_attribute(&mut header, "displayWindow", "box2i", &window);
+
+	// scanlines stored from the top down
+	add_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+	add_attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+	add_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+	add_attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+	header.push(0);
+
+	let mut file = BufWriter::new(File::create(path)?);
+	file.write_all(&header)?;
+
+	// offset table, one entry per scanline, each holding its y, its size and its data
+	let line_size: usize = channels
+		.iter()
+		.map(|channel| match channel.pixel_type {
+			ExrPixelType::Half => width * 2,
+			ExrPixelType::Float => width * 4,
+		})
+		.sum();
+	let first_line = header.len() + height * 8;
+	for line in 0..height {
+		let offset = first_line + line * (8 + line_size);
+		file.write_all(&(offset as u64).to_le_bytes())?;
+	}
+
+	for y in 0..height {
+		file.write_all(&(y as i32).to_le_bytes())?;
+		file.write_all(&(line_size as i32).to_le_bytes())?;
+		// one channel after another
+		let row = (height - 1 - y) * width;
+		for channel in &channels {
+			for &value in &channel.values[row..row + width] {
+				match channel.pixel_type {
+					ExrPixelType::Half => file.write_all(&f32_to_half(value).to_le_bytes())?,
+					ExrPixelType::Float => file.write_all(&value.to_le_bytes())?,
+				}
+			}
+		}
+	}
+
+	file.flush()
+}
+
+fn add_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+	header.extend_from_slice(name.as_bytes());
+	header.push(0);
+	header.extend_from_slice(kind.as_bytes());
+	header.push(0);
+	header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+	header.extend_from_slice(value);
+}
+
+// bits of the nearest IEEE 754 half precision float to `value`, overflowing to infinity
+fn f32_to_half(value: f32) -> u16 {
+	let bits = value.to_bits();
+	let sign = ((bits >> 16) & 0x8000) as u16;
+	let exponent = ((bits >> 23) & 0xff) as i32;
+	let mantissa = bits & 0x7f_ffff;
+
+	if exponent == 0xff {
+		// infinity, or a quiet NaN
+		return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+	}
+
+	let exponent = exponent - 127 + 15;
+	if exponent >= 0x1f {
+		return sign | 0x7c00;
+	}
+
+	if exponent <= 0 {
+		// subnormal, or too small and flushed to zero
+		if exponent < -10 {
+			return sign;
+		}
+		let mantissa = mantissa | 0x80_0000;
+		let shift = (14 - exponent) as u32;
+		let half = mantissa >> shift;
+		// round to nearest, ties to even
+		let remainder = mantissa & ((1 << shift) - 1);
+		let halfway = 1 << (shift - 1);
+		let round = remainder > halfway || (remainder == halfway && half & 1 == 1);
+		return sign | (half + round as u32) as u16;
+	}
+
+	let half = ((exponent as u32) << 10) | (mantissa >> 13);
+	let remainder = mantissa & 0x1fff;
+	// a carry out of the mantissa correctly bumps the exponent, up to infinity
+	let round = remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1);
+	sign | (half + round as u32) as u16
+}
+
+/// Write `pixels`, RGB rows from the bottom as given by `Cells::to_vec`, to a `width` by
+/// `height` Radiance .hdr file, whose shared exponent per pixel keeps the full range of
+/// the render.
+pub fn save_hdr(path: &Path, width: usize, height: usize, pixels: &[f32]) -> io::Result<()> {
+	let mut file = BufWriter::new(File::create(path)?);
+	write!(
+		file,
+		"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+		height, width
+	)?;
+
+	// scanlines from the top down
+	for row in pixels.chunks(width * 3).rev() {
+		let rgbe: Vec<[u8; 4]> = row
+			.chunks(3)
+			.map(|pixel| to_rgbe(pixel[0], pixel[1], pixel[2]))
+			.collect();
+
+		// each scanline starts with a marker and its width, then holds each component
+		// of every pixel in turn, run length encoded
+		file.write_all(&[2, 2, (width >> 8) as u8, width as u8])?;
+		for component in 0..4 {
+			let values: Vec<u8> = rgbe.iter().map(|pixel| pixel[component]).collect();
+			write_rle(&mut file, &values)?;
+		}
+	}
+
+	file.flush()
+}
+
+// RGB sharing the exponent of the brightest component
+fn to_rgbe(r: f32, g: f32, b: f32) -> [u8; 4] {
+	let (r, g, b) = (r.max(0.0), g.max(0.0), b.max(0.0));
+	let brightest = r.max(g).max(b);
+	if brightest < 1e-32 {
+		return [0; 4];
+	}
+
+	// brightest = mantissa * 2^exponent, with the mantissa in [0.5, 1)
+	let exponent = brightest.log2().floor() as i32 + 1;
+	let scale = 256.0 / (exponent as f32).exp2();
+	[
+		(r * scale).min(255.0) as u8,
+		(g * scale).min(255.0) as u8,
+		(b * scale).min(255.0) as u8,
+		(exponent + 128).clamp(0, 255) as u8,
+	]
+}
+
+// runs of more than two equal bytes as a count over 128 then the byte, everything else
+// as a count of up to 128 then the bytes themselves
+fn write_rle<W: Write>(out: &mut W, values: &[u8]) -> io::Result<()> {
+	let run_length = |start: usize| {
+		values[start..]
+			.iter()
+			.take(127)
+			.take_while(|&&value| value == values[start])
+			.count()
+	};
+
+	let mut position = 0;
+	while position < values.len() {
+		let run = run_length(position);
+		if run > 2 {
+			out.write_all(&[128 + run as u8, values[position]])?;
+			position += run;
+			continue;
+		}
+
+		// literal bytes up to the next run worth encoding
+		let start = position;
+		while position < values.len() && position - start < 128 && run_length(position) <= 2 {
+			position += 1;
+		}
+		out.write_all(&[(position - start) as u8])?;
+		out.write_all(&values[start..position])?;
+	}
+
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::fs;
+
+	// somewhere to write `name` that no other test run is using
+	fn temp_path(name: &str) -> ::std::path::PathBuf {
+		::std::env::temp_dir().join(format!("pstet-{}-{}", ::std::process::id(), name))
+	}
+
+	fn read_i32(bytes: &[u8], at: usize) -> i32 {
+		let mut word = [0; 4];
+		word.copy_from_slice(&bytes[at..at + 4]);
+		i32::from_le_bytes(word)
+	}
+
+	fn read_f32(bytes: &[u8], at: usize) -> f32 {
+		f32::from_bits(read_i32(bytes, at) as u32)
+	}
+
+	// the bytes up to the next null, and where the rest starts
+	fn read_string(bytes: &[u8], at: usize) -> (String, usize) {
+		let end = at + bytes[at..].iter().position(|&byte| byte == 0).unwrap();
+		(String::from_utf8(bytes[at..end].to_vec()).unwrap(), end + 1)
+	}
+
+	#[test]
+	fn exr_round_trips() {
+		let (width, height) = (3, 2);
+		// each value says which channel, row and column it came from
+		let channel = |name: &str, pixel_type: ExrPixelType, base: f32| ExrChannel {
+			name: name.to_string(),
+			pixel_type,
+			values: (0..width * height).map(|index| base + index as f32).collect(),
+		};
+		let channels = vec![
+			channel("Z", ExrPixelType::Float, 100.0),
+			channel("B", ExrPixelType::Half, 200.0),
+			channel("N.X", ExrPixelType::Float, 300.0),
+		];
+
+		let path = temp_path("round-trip.exr");
+		save_exr(&path, width, height, &channels).unwrap();
+		let bytes = fs::read(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		assert_eq!(&bytes[..8], &[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+		// attributes as name, type, size and value, up to an empty name
+		let mut attributes = vec![];
+		let mut at = 8;
+		loop {
+			let (name, next) = read_string(&bytes, at);
+			if name.is_empty() {
+				at = next;
+				break;
+			}
+			let (kind, next) = read_string(&bytes, next);
+			let size = read_i32(&bytes, next) as usize;
+			attributes.push((name, kind, bytes[next + 4..next + 4 + size].to_vec()));
+			at = next + 4 + size;
+		}
+		let attribute = |name: &str| {
+			attributes
+				.iter()
+				.find(|attribute| attribute.0 == name)
+				.unwrap_or_else(|| panic!("no {} attribute", name))
+		};
+
+		let (_, kind, list) = attribute("channels");
+		assert_eq!(kind, "chlist");
+		let mut names = vec![];
+		let mut types = vec![];
+		let mut entry = 0;
+		while list[entry] != 0 {
+			let (name, next) = read_string(list, entry);
+			names.push(name);
+			types.push(read_i32(list, next));
+			entry = next + 16;
+		}
+		assert_eq!(names, vec!["B", "N.X", "Z"]);
+		assert_eq!(types, vec![1, 2, 2]);
+
+		let (_, kind, window) = attribute("dataWindow");
+		assert_eq!(kind, "box2i");
+		let corners: Vec<i32> = (0..4).map(|i| read_i32(window, i * 4)).collect();
+		assert_eq!(corners, vec![0, 0, 2, 1]);
+		assert_eq!(attribute("lineOrder").2, vec![0]);
+		assert_eq!(attribute("compression").2, vec![0]);
+
+		// a half and two floats per pixel
+		let line_size = width * (2 + 4 + 4);
+		let offsets: Vec<usize> = (0..height)
+			.map(|line| {
+				let mut word = [0; 8];
+				word.copy_from_slice(&bytes[at + line * 8..at + line * 8 + 8]);
+				u64::from_le_bytes(word) as usize
+			})
+			.collect();
+		for (line, &offset) in offsets.iter().enumerate() {
+			assert_eq!(read_i32(&bytes, offset), line as i32);
+			assert_eq!(read_i32(&bytes, offset + 4) as usize, line_size);
+		}
+		assert_eq!(offsets[1], offsets[0] + 8 + line_size);
+		assert_eq!(offsets[1] + 8 + line_size, bytes.len());
+
+		// the first scanline is the top row, the last of those given
+		let scanline = offsets[0] + 8;
+		let top = width * (height - 1);
+		let halves: Vec<u16> = (0..width)
+			.map(|x| u16::from_le_bytes([bytes[scanline + x * 2], bytes[scanline + x * 2 + 1]]))
+			.collect();
+		let expected: Vec<u16> = (0..width)
+			.map(|x| f32_to_half(200.0 + (top + x) as f32))
+			.collect();
+		assert_eq!(halves, expected);
+		let floats = scanline + width * 2;
+		for x in 0..width {
+			assert_eq!(read_f32(&bytes, floats + x * 4), 300.0 + (top + x) as f32);
+			assert_eq!(read_f32(&bytes, floats + (width + x) * 4), 100.0 + (top + x) as f32);
+		}
+	}
+
+	#[test]
+	fn hdr_round_trips() {
+		let (width, height) = (8, 2);
+		// bottom row black, top row getting brighter
+		let pixels: Vec<f32> = (0..width * height)
+			.flat_map(|index| {
+				let value = if index < width { 0.0 } else { (index - width + 1) as f32 };
+				vec![value, value / 2.0, 0.0]
+			})
+			.collect();
+
+		let path = temp_path("round-trip.hdr");
+		save_hdr(&path, width, height, &pixels).unwrap();
+		let bytes = fs::read(&path).unwrap();
+		fs::remove_file(&path).unwrap();
+
+		let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 8\n";
+		assert_eq!(&bytes[..header.len()], &header[..]);
+
+		// undo the run length encoding of each component of each scanline in turn
+		let mut at = header.len();
+		let mut scanlines = vec![];
+		for _ in 0..height {
+			assert_eq!(&bytes[at..at + 4], &[2, 2, 0, 8]);
+			at += 4;
+			let mut components = vec![];
+			for _ in 0..4 {
+				let mut values = vec![];
+				while values.len() < width {
+					let count = bytes[at] as usize;
+					if count > 128 {
+						values.extend(::std::iter::repeat_n(bytes[at + 1], count - 128));
+						at += 2;
+					} else {
+						values.extend_from_slice(&bytes[at + 1..at + 1 + count]);
+						at += 1 + count;
+					}
+				}
+				assert_eq!(values.len(), width);
+				components.push(values);
+			}
+			scanlines.push(components);
+		}
+		assert_eq!(at, bytes.len());
+
+		// the first scanline is the top row
+		let top = &scanlines[0];
+		let stored: Vec<[u8; 4]> = (0..width)
+			.map(|x| [top[0][x], top[1][x], top[2][x], top[3][x]])
+			.collect();
+		let expected: Vec<[u8; 4]> = (1..=width)
+			.map(|value| to_rgbe(value as f32, value as f32 / 2.0, 0.0))
+			.collect();
+		assert_eq!(stored, expected);
+		assert!(scanlines[1].iter().all(|values| values.iter().all(|&value| value == 0)));
+	}
+
+	fn rle(values: &[u8]) -> Vec<u8> {
+		let mut out = vec![];
+		write_rle(&mut out, values).unwrap();
+		out
+	}
+
+	#[test]
+	fn rle_packs_runs_and_literals() {
+		assert_eq!(rle(&[7, 7, 7, 7]), vec![132, 7]);
+		// runs of two aren't worth a packet of their own
+		assert_eq!(rle(&[1, 2, 2, 3]), vec![4, 1, 2, 2, 3]);
+		assert_eq!(rle(&[1, 2, 5, 5, 5]), vec![2, 1, 2, 131, 5]);
+		assert_eq!(rle(&[]), Vec::<u8>::new());
+	}
+
+	#[test]
+	fn rle_splits_long_packets() {
+		// runs top out at 127, literals at 128
+		assert_eq!(rle(&[9; 200]), vec![255, 9, 128 + 73, 9]);
+
+		let literal: Vec<u8> = (0..130).map(|i| (i % 2) as u8).collect();
+		let packed = rle(&literal);
+		assert_eq!(packed[0], 128);
+		assert_eq!(&packed[1..129], &literal[..128]);
+		assert_eq!(&packed[129..], &[2, 0, 1]);
+	}
+
+	#[test]
+	fn rgbe_shares_the_brightest_exponent() {
+		// 1 is 0.5 * 2^1
+		assert_eq!(to_rgbe(1.0, 0.5, 0.25), [128, 64, 32, 129]);
+		assert_eq!(to_rgbe(0.5, 0.0, 0.0), [128, 0, 0, 128]);
+		// just under a power of two keeps the lower exponent
+		assert_eq!(to_rgbe(0.999, 0.0, 0.0), [255, 0, 0, 128]);
+		assert_eq!(to_rgbe(1000.0, 0.0, 0.0)[3], 128 + 10);
+	}
+
+	#[test]
+	fn rgbe_stores_black_and_negatives_as_zero() {
+		assert_eq!(to_rgbe(0.0, 0.0, 0.0), [0; 4]);
+		assert_eq!(to_rgbe(1e-35, 0.0, 0.0), [0; 4]);
+		assert_eq!(to_rgbe(-1.0, -2.0, -3.0), [0; 4]);
+		assert_eq!(to_rgbe(-1.0, 1.0, 0.0), [0, 128, 0, 129]);
+	}
+
+	#[test]
+	fn half_rounds_and_saturates() {
+		assert_eq!(f32_to_half(0.0), 0x0000);
+		assert_eq!(f32_to_half(-0.0), 0x8000);
+		assert_eq!(f32_to_half(1.0), 0x3c00);
+		assert_eq!(f32_to_half(-2.0), 0xc000);
+		assert_eq!(f32_to_half(65504.0), 0x7bff);
+		// past the largest half, and halfway to the next representable value
+		assert_eq!(f32_to_half(65536.0), 0x7c00);
+		assert_eq!(f32_to_half(65520.0), 0x7c00);
+		assert_eq!(f32_to_half(f32::INFINITY), 0x7c00);
+		assert_eq!(f32_to_half(f32::NAN) & 0x7e00, 0x7e00);
+		// smallest subnormal, and half of it rounding to even
+		assert_eq!(f32_to_half(2f32.powi(-24)), 0x0001);
+		assert_eq!(f32_to_half(2f32.powi(-25)), 0x0000);
+		assert_eq!(f32_to_half(1.5 * 2f32.powi(-24)), 0x0002);
+		// ties between 1 and the next half go to even
+		assert_eq!(f32_to_half(1.0 + 2f32.powi(-11)), 0x3c00);
+		assert_eq!(f32_to_half(1.0 + 3.0 * 2f32.powi(-11)), 0x3c02);
+	}
+}
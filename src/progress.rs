@@ -0,0 +1,103 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use types::*;
+
+/// Counts of the work a render has done, updated by its threads as they go.
+pub struct Progress {
+	started: Instant,
+	// pixels that have taken all the samples they are going to
+	pixels_done: AtomicUsize,
+	tiles_done: AtomicUsize,
+	tiles_total: usize,
+	pixels_total: usize,
+	// camera rays traced, not counting reflections or shadows
+	rays: AtomicUsize,
+}
+
+impl Progress {
+	pub fn new(tiles_total: usize, pixels_total: usize) -> Progress {
+		Progress {
+			started: Instant::now(),
+			pixels_done: AtomicUsize::new(0),
+			tiles_done: AtomicUsize::new(0),
+			tiles_total,
+			pixels_total,
+			rays: AtomicUsize::new(0),
+		}
+	}
+
+	pub fn finish_pixel(&self) {
+		self.pixels_done.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn finish_tile(&self) {
+		self.tiles_done.fetch_add(1, Ordering::Relaxed);
+	}
+
+	pub fn add_rays(&self, rays: usize) {
+		self.rays.fetch_add(rays, Ordering::Relaxed);
+	}
+
+	/// How far along the render is right now.
+	pub fn report(&self) -> ProgressReport {
+		let elapsed = self.started.elapsed();
+		let seconds = elapsed.as_secs_f64();
+		let pixels_done = self.pixels_done.load(Ordering::Relaxed);
+		let fraction = pixels_done as f64 / self.pixels_total as f64;
+
+		ProgressReport {
+			fraction,
+			tiles: if PROGRESSIVE {
+				None
+			} else {
+				Some((self.tiles_done.load(Ordering::Relaxed), self.tiles_total))
+			},
+			rays_per_second: if seconds > 0.0 {
+				self.rays.load(Ordering::Relaxed) as f64 / seconds
+			} else {
+				0.0
+			},
+			// assuming the rest of the image takes as long per pixel as the part done so far
+			remaining: if pixels_done > 0 {
+				Some(elapsed.mul_f64((1.0 - fraction) / fraction))
+			} else {
+				None
+			},
+		}
+	}
+}
+
+/// A snapshot of a render's progress, which displays as e.g.
+/// "42.0% 3.21M rays/s 1m 05s left".
+#[derive(Debug, Clone, Copy)]
+pub struct ProgressReport {
+	// of the pixels finished, from 0 to 1
+	pub fraction: f64,
+	// finished and total, when tiles are finished one at a time rather than refined in passes
+	pub tiles: Option<(usize, usize)>,
+	pub rays_per_second: f64,
+	// none until the first pixel finishes
+	pub remaining: Option<Duration>,
+}
+
+impl fmt::Display for ProgressReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:.1}%", self.fraction * 100.0)?;
+		if let Some((done, total)) = self.tiles {
+			write!(f, " tile {}/{}", done, total)?;
+		}
+		write!(f, " {:.2}M rays/s", self.rays_per_second / 1e6)?;
+		match self.remaining {
+			Some(remaining) => {
+				let seconds = remaining.as_secs();
+				if seconds >= 3600 {
+					write!(f, " {}h {:02}m left", seconds / 3600, seconds / 60 % 60)
+				} else {
+					write!(f, " {}m {:02}s left", seconds / 60, seconds % 60)
+				}
+			}
+			None => write!(f, " estimating time left"),
+		}
+	}
+}
@@ -0,0 +1,457 @@
+use camera::Camera;
+use cgmath::prelude::*;
+use film::Film;
+use progress::{Progress, ProgressReport};
+use sampler::Sampler;
+use scene::Scene;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use texture::Texture;
+use tiles::{make_tiles, ActiveTiles, Tile};
+use types::*;
+use integrator::trace_primary;
+use util::luminance;
+
+fn get_xy(index: usize, width: usize) -> (usize, usize) {
+	(index % width, index / width)
+}
+
+// trace one sample of pixel (cell_x, cell_y), returning where it landed in pixels, its color
+// and the surface it hit
+fn trace_sample(
+	cell_x: usize,
+	cell_y: usize,
+	context: &RenderContext,
+	sampler: &mut Sampler,
+) -> (V2, Color, SurfaceChannels) {
+	// in pixels from the corner of the image
+	let offset = sampler.get_2d();
+	let position = V2 {
+		x: cell_x as f32 + offset.x,
+		y: cell_y as f32 + offset.y,
+	};
+	let lens = sampler.get_2d();
+	let time = sampler.get_1d();
+
+	let film_point = V2 {
+		x: position.x / context.cells.width() as f32,
+		y: position.y / context.cells.height() as f32,
+	};
+
+	// no rays outside the projection, e.g. around a fisheye's image circle. Stereo pairs
+	// report the surface the first eye saw
+	let rays = context.camera.rays(film_point, lens, time);
+	context.progress.add_rays(rays.iter().flatten().count());
+
+	let mut surface = None;
+	let color = rays
+		.iter()
+		.flatten()
+		.map(|&(ref ray, filter)| {
+			let (color, hit) = trace_primary(ray, context.scene, context.textures, sampler);
+			surface.get_or_insert(hit);
+			color.mul_element_wise(filter)
+		})
+		.sum::<V3>();
+
+	(position, color, surface.unwrap_or_default())
+}
+
+// everything a render thread needs to trace its share of the image
+struct RenderContext<'a> {
+	cells: &'a Cells,
+	film: &'a Film,
+	tiles: &'a [Tile],
+	active_tiles: &'a ActiveTiles,
+	control: &'a JobControl,
+	progress: &'a Progress,
+	camera: &'a Camera,
+	scene: &'a Scene,
+	textures: &'a [Texture],
+}
+
+impl<'a> RenderContext<'a> {
+	fn cancelled(&self) -> bool {
+		self.control.cancelled.load(Ordering::Relaxed)
+	}
+}
+
+// hand tiles out to every thread in order, marking them active while they render
+fn for_each_tile<F>(context: &RenderContext, render: F)
+where
+	F: Fn(&Tile) + Sync,
+{
+	use rayon::prelude::*;
+
+	let next = AtomicUsize::new(0);
+
+	(0..rayon::current_num_threads())
+		.into_par_iter()
+		.for_each(|_| {
+			while let Some(&tile) = context.tiles.get(next.fetch_add(1, Ordering::Relaxed)) {
+				if !context.control.checkpoint() {
+					break;
+				}
+
+				context.active_tiles.start(tile);
+				render(&tile);
+				context.active_tiles.finish(tile);
+			}
+		});
+}
+
+// one sample per block of PREVIEW_SCALE pixels, filling the whole block, so there is
+// something to look at while the full resolution render gets going
+fn trace_preview(context: &RenderContext) {
+	use rayon::prelude::*;
+
+	let (width, height) = (context.cells.width(), context.cells.height());
+	let blocks_wide = width.div_ceil(PREVIEW_SCALE);
+	let blocks_high = height.div_ceil(PREVIEW_SCALE);
+
+	(0..blocks_wide * blocks_high)
+		.into_par_iter()
+		.for_each(|block| {
+			if !context.control.checkpoint() {
+				return;
+			}
+
+			let (left, bottom) = (
+				block % blocks_wide * PREVIEW_SCALE,
+				block / blocks_wide * PREVIEW_SCALE,
+			);
+			let (right, top) = (
+				(left + PREVIEW_SCALE).min(width),
+				(bottom + PREVIEW_SCALE).min(height),
+			);
+			let (cell_x, cell_y) = ((left + right) / 2, (bottom + top) / 2);
+
+			let mut sampler = Sampler::new(SAMPLER, cell_y * width + cell_x, SAMPLE_BATCH);
+			let (_, color, surface) = trace_sample(
+				cell_x,
+				cell_y,
+				context,
+				&mut sampler,
+			);
+
+			for y in bottom..top {
+				for x in left..right {
+					let index = y * width + x;
+					context.cells.set_content(index, color);
+					context.cells.set_surface(index, surface);
+				}
+			}
+		});
+}
+
+// finish each pixel in turn, taking batches of samples until it is no longer noisy
+fn trace_pixels(context: &RenderContext) {
+	for_each_tile(context, |tile| {
+		for index in tile.pixels(context.cells.width()) {
+			if context.cancelled() {
+				return;
+			}
+
+			let (cell_x, cell_y) = get_xy(index, context.cells.width());
+
+			let mut sampler = Sampler::new(SAMPLER, index, SAMPLE_BATCH);
+			let mut statistics = SampleStatistics::default();
+			let mut surface = SurfaceChannels::default();
+
+			// spend more samples where the pixel is still noisy, e.g. edges, reflections
+			// and textures
+			let mut sample = 0;
+			while sample < MAX_SAMPLES_PER_PIXEL
+				&& (sample < MIN_SAMPLES_PER_PIXEL || statistics.relative_error() > NOISE_THRESHOLD)
+			{
+				for _ in 0..SAMPLE_BATCH {
+					sampler.start_sample(sample);
+					let (position, color, hit) = trace_sample(
+						cell_x,
+						cell_y,
+						context,
+						&mut sampler,
+					);
+
+					context.film.splat(position, color);
+					statistics.add(luminance(color));
+					sample += 1;
+					surface.accumulate(hit, sample);
+				}
+			}
+
+			context.cells.data[index].set_statistics(statistics);
+			context.cells.set_surface(index, surface);
+			context.progress.finish_pixel();
+
+			// show the pixels this one's samples reached
+			for neighbour in context.film.footprint(cell_x, cell_y) {
+				context.cells.set_content(neighbour, context.film.resolve(neighbour));
+			}
+		}
+
+		context.progress.finish_tile();
+	});
+}
+
+// take one sample in every pixel per pass, showing the running mean after each, so a noisy
+// preview of the whole image appears straight away. Pixels drop out once they are no longer noisy
+fn trace_progressive(context: &RenderContext) {
+	use rayon::prelude::*;
+
+	for pass in 0..MAX_SAMPLES_PER_PIXEL {
+		let sampled = AtomicUsize::new(0);
+
+		for_each_tile(context, |tile| {
+			for index in tile.pixels(context.cells.width()) {
+				if context.cancelled() {
+					return;
+				}
+
+				let cell = &context.cells.data[index];
+				let mut statistics = cell.get_statistics();
+				if pass >= MIN_SAMPLES_PER_PIXEL && statistics.relative_error() <= NOISE_THRESHOLD
+				{
+					continue;
+				}
+
+				let (cell_x, cell_y) = get_xy(index, context.cells.width());
+				let mut sampler = Sampler::new(SAMPLER, index, SAMPLE_BATCH);
+				sampler.start_sample(pass);
+				let (position, color, hit) = trace_sample(
+					cell_x,
+					cell_y,
+					context,
+					&mut sampler,
+				);
+
+				context.film.splat(position, color);
+				statistics.add(luminance(color));
+				cell.set_statistics(statistics);
+
+				// the same test that skips the pixel in the next pass
+				let samples = pass + 1;
+				if samples == MAX_SAMPLES_PER_PIXEL
+					|| (samples >= MIN_SAMPLES_PER_PIXEL
+						&& statistics.relative_error() <= NOISE_THRESHOLD)
+				{
+					context.progress.finish_pixel();
+				}
+
+				let mut surface = cell.get_surface();
+				surface.accumulate(hit, pass + 1);
+				context.cells.set_surface(index, surface);
+				sampled.fetch_add(1, Ordering::Relaxed);
+			}
+		});
+
+		if !context.control.checkpoint() {
+			return;
+		}
+
+		(0..context.cells.data.len())
+			.into_par_iter()
+			.for_each(|index| context.cells.set_content(index, context.film.resolve(index)));
+
+		if sampled.load(Ordering::Relaxed) == 0 {
+			break;
+		}
+	}
+}
+
+// render the scene through `camera` into `cells`, under the job's control
+#[allow(clippy::too_many_arguments)]
+fn trace_rays(
+	cells: &Cells,
+	active_tiles: &ActiveTiles,
+	control: &JobControl,
+	progress: &Progress,
+	tiles: &[Tile],
+	camera: &Camera,
+	scene: &Scene,
+	textures: &[Texture],
+) {
+	use rayon::prelude::*;
+
+	let film = Film::new(FILTER, cells.width(), cells.height());
+
+	let context = RenderContext {
+		cells,
+		film: &film,
+		tiles,
+		active_tiles,
+		control,
+		progress,
+		camera,
+		scene,
+		textures,
+	};
+
+	// forget what a previous render learnt about each pixel's noise, and how near it saw
+	for cell in cells.data.iter() {
+		cell.set_statistics(SampleStatistics::default());
+	}
+	cells.reset_nearest_depth();
+
+	trace_preview(&context);
+
+	if PROGRESSIVE {
+		trace_progressive(&context);
+	} else {
+		trace_pixels(&context);
+	}
+
+	if context.cancelled() {
+		return;
+	}
+
+	// neighbouring pixels finishing together may have shown each other's results out of order
+	(0..cells.data.len())
+		.into_par_iter()
+		.for_each(|index| cells.set_content(index, film.resolve(index)));
+}
+
+// shared between a job and its render threads, which check it between tiles and passes
+#[derive(Default)]
+struct JobControl {
+	cancelled: AtomicBool,
+	paused: Mutex<bool>,
+	resumed: Condvar,
+}
+
+impl JobControl {
+	// wait out any pause, then whether the render should carry on
+	fn checkpoint(&self) -> bool {
+		let mut paused = self.paused.lock().unwrap();
+		while *paused && !self.cancelled.load(Ordering::Relaxed) {
+			paused = self.resumed.wait(paused).unwrap();
+		}
+		!self.cancelled.load(Ordering::Relaxed)
+	}
+
+	fn set_paused(&self, paused: bool) {
+		*self.paused.lock().unwrap() = paused;
+		self.resumed.notify_all();
+	}
+
+	fn cancel(&self) {
+		self.cancelled.store(true, Ordering::Relaxed);
+		// wake paused threads so they can see they were cancelled
+		let _paused = self.paused.lock().unwrap();
+		self.resumed.notify_all();
+	}
+}
+
+/// A render of a scene into `Cells` on a background thread, which can be paused,
+/// cancelled or restarted from another thread without killing the process.
+pub struct RenderJob {
+	cells: Cells,
+	active_tiles: ActiveTiles,
+	scene: Arc<Scene>,
+	textures: Arc<Vec<Texture>>,
+	control: Arc<JobControl>,
+	progress: Arc<Progress>,
+	thread: Option<JoinHandle<()>>,
+}
+
+impl RenderJob {
+	/// Start rendering `scene` through `camera`, drawing into `cells` and listing the
+	/// tiles being worked on in `active_tiles`.
+	pub fn start(
+		cells: &Cells,
+		active_tiles: &ActiveTiles,
+		camera: Camera,
+		scene: &Arc<Scene>,
+		textures: &Arc<Vec<Texture>>,
+	) -> RenderJob {
+		let mut job = RenderJob {
+			cells: cells.clone(),
+			active_tiles: active_tiles.clone(),
+			scene: scene.clone(),
+			textures: textures.clone(),
+			control: Arc::new(JobControl::default()),
+			progress: Arc::new(Progress::new(0, cells.data.len())),
+			thread: None,
+		};
+		job.spawn(camera);
+		job
+	}
+
+	fn spawn(&mut self, camera: Camera) {
+		let cells = self.cells.clone();
+		let active_tiles = self.active_tiles.clone();
+		let control = self.control.clone();
+		let scene = self.scene.clone();
+		let textures = self.textures.clone();
+
+		let tiles = make_tiles(cells.width(), cells.height(), TILE_SIZE, TILE_ORDER);
+		self.progress = Arc::new(Progress::new(tiles.len(), cells.data.len()));
+		let progress = self.progress.clone();
+
+		self.thread = Some(::std::thread::spawn(move || {
+			trace_rays(
+				&cells,
+				&active_tiles,
+				&control,
+				&progress,
+				&tiles,
+				&camera,
+				&scene,
+				&textures,
+			)
+		}));
+	}
+
+	/// Stop rendering, waiting for the render threads to stop writing to the cells.
+	pub fn cancel(&mut self) {
+		self.control.cancel();
+		self.wait();
+	}
+
+	/// Let the tiles being rendered finish, then hold the render until it is resumed.
+	pub fn pause(&self) {
+		self.control.set_paused(true);
+	}
+
+	pub fn resume(&self) {
+		self.control.set_paused(false);
+	}
+
+	pub fn is_paused(&self) -> bool {
+		*self.control.paused.lock().unwrap()
+	}
+
+	/// Whether the render has stopped, either finished or cancelled.
+	pub fn is_finished(&self) -> bool {
+		self.thread
+			.as_ref()
+			.is_none_or(|thread| thread.is_finished())
+	}
+
+	/// How far the render has got since it last started.
+	pub fn progress(&self) -> ProgressReport {
+		self.progress.report()
+	}
+
+	/// Cancel the render and start again from the beginning through `camera`.
+	/// A paused render starts again unpaused.
+	pub fn restart(&mut self, camera: Camera) {
+		self.cancel();
+		self.control = Arc::new(JobControl::default());
+		self.spawn(camera);
+	}
+
+	/// Block until the render finishes or is cancelled.
+	pub fn wait(&mut self) {
+		if let Some(thread) = self.thread.take() {
+			thread.join().unwrap();
+		}
+	}
+}
+
+impl Drop for RenderJob {
+	fn drop(&mut self) {
+		self.cancel();
+	}
+}
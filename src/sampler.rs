@@ -0,0 +1,208 @@
+use types::*;
+
+// prime bases for the Halton dimensions, past which samples are plain random
+const PRIMES: [u32; 32] = [
+	2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89,
+	97, 101, 103, 107, 109, 113, 127, 131,
+];
+
+/// Sequence used to place the samples taken in each pixel.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum SamplerKind {
+	// independent uniform random samples
+	Random,
+	// one jittered sample in each stratum of the pixel, the strata shuffled per dimension
+	Stratified,
+	// Halton sequence, randomly shifted per pixel
+	Halton,
+	// pairs of dimensions from the (0, 2) Sobol sequence, shuffled and scrambled per pair
+	Sobol,
+}
+
+/// Source of the sample values in [0, 1) a pixel needs: the position in the pixel,
+/// on the lens, in time, on lights and in reflection lobes.
+/// Values depend only on the pixel, sample index and dimension, so renders are repeatable.
+#[derive(Debug, Clone)]
+pub struct Sampler {
+	kind: SamplerKind,
+	seed: u64,
+	samples_per_pixel: u32,
+	index: u32,
+	dimension: u32,
+}
+
+impl Sampler {
+	pub fn new(kind: SamplerKind, pixel: usize, samples_per_pixel: usize) -> Sampler {
+		Sampler {
+			kind,
+			seed: mix(pixel as u64 ^ 0x5851_f42d_4c95_7f2d),
+			samples_per_pixel: samples_per_pixel as u32,
+			index: 0,
+			dimension: 0,
+		}
+	}
+
+	/// Move to sample `index` of the pixel, starting again from the first dimension.
+	pub fn start_sample(&mut self, index: usize) {
+		self.index = index as u32;
+		self.dimension = 0;
+	}
+
+	pub fn get_1d(&mut self) -> f32 {
+		let dimension = self.dimension;
+		self.dimension += 1;
+
+		match self.kind {
+			SamplerKind::Random => self.random(dimension, 0),
+			SamplerKind::Stratified => {
+				let stratum = self.shuffled_index(dimension) % self.samples_per_pixel.max(1);
+				(stratum as f32 + self.random(dimension, 0)) / self.samples_per_pixel as f32
+			}
+			SamplerKind::Halton => self.halton(dimension),
+			SamplerKind::Sobol => {
+				let index = self.shuffled_index(dimension);
+				to_unit(index.reverse_bits() ^ self.scramble(dimension, 0))
+			}
+		}
+	}
+
+	pub fn get_2d(&mut self) -> V2 {
+		let dimension = self.dimension;
+		self.dimension += 2;
+
+		match self.kind {
+			SamplerKind::Random => V2::new(self.random(dimension, 0), self.random(dimension, 1)),
+			SamplerKind::Stratified => {
+				// square grid of strata, as large as the sample count allows
+				let side = (self.samples_per_pixel as f32).sqrt().max(1.0) as u32;
+				let stratum = self.shuffled_index(dimension) % (side * side);
+				V2::new(
+					((stratum % side) as f32 + self.random(dimension, 0)) / side as f32,
+					((stratum / side) as f32 + self.random(dimension, 1)) / side as f32,
+				)
+			}
+			SamplerKind::Halton => V2::new(self.halton(dimension), self.halton(dimension + 1)),
+			SamplerKind::Sobol => {
+				let index = self.shuffled_index(dimension);
+				V2::new(
+					to_unit(index.reverse_bits() ^ self.scramble(dimension, 0)),
+					to_unit(sobol_second_dimension(index) ^ self.scramble(dimension, 1)),
+				)
+			}
+		}
+	}
+
+	fn scramble(&self, dimension: u32, axis: u64) -> u32 {
+		(mix(self.seed ^ mix(u64::from(dimension) << 1 | axis)) >> 32) as u32
+	}
+
+	fn random(&self, dimension: u32, axis: u64) -> f32 {
+		let key = mix(u64::from(dimension) << 1 | axis) ^ u64::from(self.index) << 32;
+		to_unit((mix(self.seed ^ key) >> 32) as u32)
+	}
+
+	// the sample index, shuffled within each run of samples_per_pixel so dimensions
+	// don't line up with each other
+	fn shuffled_index(&self, dimension: u32) -> u32 {
+		let count = self.samples_per_pixel.max(1);
+		let run = self.index / count;
+		let seed = self.scramble(dimension, 2) ^ mix_u32(run);
+		run * count + permute(self.index % count, count, seed)
+	}
+
+	fn halton(&self, dimension: u32) -> f32 {
+		match PRIMES.get(dimension as usize) {
+			None => self.random(dimension, 0),
+			Some(&base) => {
+				// Cranley-Patterson rotation, so pixels don't all share the same points
+				let value = radical_inverse(self.index, base) + to_unit(self.scramble(dimension, 1));
+				value - value.floor()
+			}
+		}
+	}
+}
+
+fn radical_inverse(mut index: u32, base: u32) -> f32 {
+	let inverse_base = 1.0 / base as f64;
+	let mut scale = inverse_base;
+	let mut value = 0.0;
+	while index > 0 {
+		value += f64::from(index % base) * scale;
+		index /= base;
+		scale *= inverse_base;
+	}
+	(value as f32).min(ONE_MINUS_EPSILON)
+}
+
+// generator matrix of the second Sobol dimension applied to `index`, the first being
+// the bit reversal
+fn sobol_second_dimension(mut index: u32) -> u32 {
+	let mut column = 1 << 31;
+	let mut value = 0;
+	while index != 0 {
+		if index & 1 != 0 {
+			value ^= column;
+		}
+		index >>= 1;
+		column ^= column >> 1;
+	}
+	value
+}
+
+// element `index` of a pseudo-random permutation of 0..count, after Kensler's
+// "Correlated Multi-Jittered Sampling"
+fn permute(mut index: u32, count: u32, seed: u32) -> u32 {
+	let mut mask = count - 1;
+	mask |= mask >> 1;
+	mask |= mask >> 2;
+	mask |= mask >> 4;
+	mask |= mask >> 8;
+	mask |= mask >> 16;
+
+	loop {
+		index ^= seed;
+		index = index.wrapping_mul(0xe170_893d);
+		index ^= seed >> 16;
+		index ^= (index & mask) >> 4;
+		index ^= seed >> 8;
+		index = index.wrapping_mul(0x0929_eb3f);
+		index ^= seed >> 23;
+		index ^= (index & mask) >> 1;
+		index = index.wrapping_mul(1 | seed >> 27);
+		index = index.wrapping_mul(0x6935_fa69);
+		index ^= (index & mask) >> 11;
+		index = index.wrapping_mul(0x74dc_b303);
+		index ^= (index & mask) >> 2;
+		index = index.wrapping_mul(0x9e50_1cc3);
+		index ^= (index & mask) >> 2;
+		index = index.wrapping_mul(0xc860_a3df);
+		index &= mask;
+		index ^= index >> 5;
+		// cycle walk until the value lands back in range
+		if index < count {
+			break;
+		}
+	}
+
+	(index.wrapping_add(seed)) % count
+}
+
+// largest f32 below 1
+const ONE_MINUS_EPSILON: f32 = 1.0 - f32::EPSILON / 2.0;
+
+fn to_unit(bits: u32) -> f32 {
+	(bits >> 8) as f32 / (1 << 24) as f32
+}
+
+// splitmix64 finaliser
+fn mix(mut x: u64) -> u64 {
+	x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+	x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+	x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+	x ^ (x >> 31)
+}
+
+fn mix_u32(x: u32) -> u32 {
+	(mix(u64::from(x)) >> 32) as u32
+}
@@ -1,21 +1,28 @@
-use image::{load_from_memory, DynamicImage};
+use cgmath::Deg;
+use cgmath::Quaternion;
+use cgmath::Rotation3;
+use cgmath::Zero;
+use material::{Bump, Channel, Material};
+use scene_graph::*;
 use shapes::*;
+use std::sync::Arc;
+use texture::{normal_map_from_heights, perforated_image, Texture};
 use types::*;
 
+use bvh::Bvh;
+use camera::Camera;
+
 pub struct Scene {
     objects: Vec<Object2>,
     lights: Vec<Light>,
+    bvh: Bvh,
+    camera: Camera,
 }
 
 const CAT_IMAGE_BYTES: &[u8] = include_bytes!("images/CUTE-CAT.jpg");
 const CARPET_IMAGE_BYTES: &[u8] = include_bytes!("images/seamless_carpet_texture.jpg");
 
-use cgmath::prelude::*;
-use cgmath::Deg;
-use cgmath::Quaternion;
-use cgmath::Rotation3;
-
-fn transform(input: V3) -> V3 {
+fn scene_matrix() -> M4 {
     let scene_center = V3 {
         x: 17.0,
         y: 20.0,
@@ -50,18 +57,27 @@ fn transform(input: V3) -> V3 {
         None,
     );
 
-    let output = input;
-
-    let output = output + scene_center;
-
-    let output = rotation_x.rotate_vector(output);
-
-    let output = rotation_y.rotate_vector(output);
-
-    output
+    M4::from(rotation_y) * M4::from(rotation_x) * M4::from_translation(scene_center)
 }
 
 impl Scene {
+    /// Flatten a scene graph and build the acceleration structure over it.
+    pub fn new(root: &Node, camera: Camera) -> Scene {
+        let (objects, lights) = root.flatten();
+
+        let bounds = objects
+            .iter()
+            .map(|object| object.shape.bounds())
+            .collect::<Vec<_>>();
+
+        Scene {
+            bvh: Bvh::new(&bounds),
+            objects,
+            lights,
+            camera,
+        }
+    }
+
     pub fn objects(&self) -> &Vec<Object2> {
         &self.objects
     }
@@ -70,180 +86,412 @@ impl Scene {
         &self.lights
     }
 
-    pub fn initialise(textures: &mut Vec<DynamicImage>) -> Scene {
-        textures.push(::image::load_from_memory(CAT_IMAGE_BYTES).unwrap());
-        textures.push(::image::load_from_memory(CARPET_IMAGE_BYTES).unwrap());
-
-        let mut objects = vec![];
-        objects.append(&mut make_cube(
-            true,
-            V3 {
-                x: 0.9,
-                y: 0.5,
-                z: 0.0,
-            },
-            Surface::Diffuse,
+    pub fn bvh(&self) -> &Bvh {
+        &self.bvh
+    }
+
+    pub fn camera(&self) -> &Camera {
+        &self.camera
+    }
+
+    pub fn initialise(textures: &mut Vec<Texture>) -> Scene {
+        textures.push(Texture::Image(
+            ::image::load_from_memory(CAT_IMAGE_BYTES).unwrap(),
         ));
+        let carpet = ::image::load_from_memory(CARPET_IMAGE_BYTES).unwrap();
+        // the weave in relief, with the bright threads standing out
+        let carpet_normals = normal_map_from_heights(&carpet, 4.0);
+        textures.push(Texture::Image(carpet));
+        let carpet_normals_id = textures.len();
+        textures.push(Texture::Image(carpet_normals));
+        // dents hammered into the orange sphere
+        let dents_id = textures.len();
+        textures.push(Texture::Noise {
+            scale: 1.5,
+            color: V3::new(1.0, 1.0, 1.0),
+        });
+        let grille_id = textures.len();
+        textures.push(Texture::Image(perforated_image(
+            256,
+            8,
+            ::image::Rgba([200, 200, 210, 255]),
+        )));
+
+        let root = Node::new("scene")
+            .with_transform(scene_matrix())
+            .with_child(make_table(dents_id))
+            .with_child(make_picture())
+            .with_child(make_carpet(carpet_normals_id))
+            .with_child(make_screen(grille_id))
+            .with_child(make_swatches(textures))
+            .with_child(
+                Node::new("lights")
+                    .with_light(Light {
+                        position: V3 {
+                            x: -29.0,
+                            y: -10.0,
+                            z: 13.0,
+                        },
+                        brightness: 40.0,
+                        radius: 0.0,
+                    })
+                    .with_light(Light {
+                        position: V3 {
+                            x: 25.0,
+                            y: 19.0,
+                            z: 19.0,
+                        },
+                        brightness: 50.0,
+                        radius: 0.0,
+                    })
+                    .with_light(Light {
+                        position: V3 {
+                            x: 0.0,
+                            y: -29.0,
+                            z: 19.0,
+                        },
+                        brightness: 60.0,
+                        radius: 0.0,
+                    }),
+            );
+
+        Scene::new(&root, CAMERA.camera())
+    }
+}
+
+// open box with the spheres and tetrahedron sitting in it
+// `dents` is the texture ID of the orange sphere's height map
+fn make_table(dents: usize) -> Node {
+    let tetrahedron_pos = V3 {
+        x: -3.0,
+        y: 10.0,
+        z: 3.0,
+    };
+
+    let tetrahedron_rotation = Quaternion::from_axis_angle(V3::unit_z(), Deg(60.0));
+    // spinning a little during the frame
+    let tetrahedron_end_rotation = Quaternion::from_axis_angle(V3::unit_z(), Deg(75.0));
 
+    let tetrahedron_size = 4.0;
+
+    Node::new("table")
+        .with_geometry(Geometry {
+            shape: Arc::new(make_cube(true)),
+            material: Material {
+                diffuse: Channel::constant(V3 {
+                    x: 0.9,
+                    y: 0.5,
+                    z: 0.0,
+                }),
+                shininess: 20.0,
+                ..Material::default()
+            },
+        })
         // orange sphere
-        objects.push(Object2 {
-            shape: Shape::Sphere(Sphere {
-                center: transform(V3 {
+        .with_geometry(Geometry {
+            shape: Arc::new(Shape::Sphere(Sphere {
+                center: V3 {
                     x: -5.0,
                     y: -5.0,
                     z: 3.0,
+                },
+                radius: 2.55,
+                velocity: V3::zero(),
+            })),
+            material: Material {
+                diffuse: Channel::constant(V3 {
+                    x: 0.9,
+                    y: 0.1,
+                    z: 0.0,
                 }),
-                radius: 6.5,
-            }),
-            surface: Surface::Diffuse,
-            color: V3 {
-                x: 0.9,
-                y: 0.1,
-                z: 0.0,
+                shininess: 80.0,
+                bump: Bump::HeightMap(dents, 0.02),
+                ..Material::default()
             },
-            shininess: 80.0,
-        });
-
+        })
         // green sphere
-        objects.push(Object2 {
-            shape: Shape::Sphere(Sphere {
-                center: transform(V3 {
+        .with_geometry(Geometry {
+            shape: Arc::new(Shape::Sphere(Sphere {
+                center: V3 {
                     x: -1.0,
                     y: -16.0,
                     z: 3.0,
+                },
+                radius: 1.73,
+                // rolling across the table while the shutter is open
+                velocity: V3 {
+                    x: 0.0,
+                    y: 2.0,
+                    z: 0.0,
+                },
+            })),
+            material: Material {
+                diffuse: Channel::constant(V3 {
+                    x: 0.0,
+                    y: 1.0,
+                    z: 0.3,
                 }),
-                radius: 3.0,
-            }),
-            surface: Surface::Diffuse,
-            color: V3 {
-                x: 0.0,
-                y: 1.0,
-                z: 0.3,
+                shininess: 40.0,
+                ..Material::default()
             },
-            shininess: 40.0,
-        });
-
+        })
         // reflective blue sphere
-        objects.push(Object2 {
-            shape: Shape::Sphere(Sphere {
-                center: transform(V3 {
+        .with_geometry(Geometry {
+            shape: Arc::new(Shape::Sphere(Sphere {
+                center: V3 {
                     x: 6.0,
                     y: 0.0,
                     z: 3.0,
+                },
+                radius: 3.87,
+                velocity: V3::zero(),
+            })),
+            material: Material {
+                diffuse: Channel::constant(V3 {
+                    x: 0.0,
+                    y: 0.0,
+                    z: 1.0,
                 }),
-                radius: 15.0,
-            }),
-            surface: Surface::Reflective(0.95),
-            color: V3 {
-                x: 0.0,
-                y: 0.0,
-                z: 1.0,
+                shininess: 40.0,
+                reflectivity: Channel::constant(0.95),
+                ..Material::default()
             },
-            shininess: 40.0,
-        });
+        })
+        .with_child(
+            Node::new("tetrahedron")
+                .with_transform(
+                    M4::from_translation(tetrahedron_pos)
+                        * M4::from(tetrahedron_rotation)
+                        * M4::from_scale(tetrahedron_size),
+                )
+                .with_motion(
+                    M4::from_translation(tetrahedron_pos)
+                        * M4::from(tetrahedron_end_rotation)
+                        * M4::from_scale(tetrahedron_size),
+                )
+                .with_geometry(Geometry {
+                    shape: Arc::new(make_tetrahedron()),
+                    material: Material {
+                        diffuse: Channel::constant(V3 {
+                            x: 0.2,
+                            y: 0.7,
+                            z: 0.4,
+                        }),
+                        shininess: 10.0,
+                        ..Material::default()
+                    },
+                }),
+        )
+}
 
-        let cat_triangle_vertices = [
-            transform(V3 {
-                x: -5.0,
-                y: 27.5,
-                z: 30.0,
-            }),
-            transform(V3 {
-                x: -5.0,
-                y: 27.5,
-                z: 0.0,
-            }),
-            transform(V3 {
-                x: 17.0,
-                y: 27.5,
-                z: 0.0,
-            }),
-            transform(V3 {
-                x: 17.0,
-                y: 27.5,
-                z: 30.0,
-            }),
-        ];
-
-        // cat triangle 1
-        objects.push(Object2 {
-            shape: Shape::Triangle(Triangle::new_with_uv(
-                [
-                    cat_triangle_vertices[0],
-                    cat_triangle_vertices[1],
-                    cat_triangle_vertices[2],
-                ],
+// cat picture standing at the back of the table
+fn make_picture() -> Node {
+    let positions = [
+        V3 {
+            x: -5.0,
+            y: 27.5,
+            z: 30.0,
+        },
+        V3 {
+            x: -5.0,
+            y: 27.5,
+            z: 0.0,
+        },
+        V3 {
+            x: 17.0,
+            y: 27.5,
+            z: 0.0,
+        },
+        V3 {
+            x: 17.0,
+            y: 27.5,
+            z: 30.0,
+        },
+    ];
+
+    Node::new("picture")
+        .with_geometry(Geometry {
+            shape: Arc::new(Shape::Triangle(Triangle::new_with_uv(
+                [positions[0], positions[1], positions[2]],
                 [
                     V2 { x: 0.0, y: 0.0 },
                     V2 { x: 0.0, y: 1.0 },
                     V2 { x: 1.0, y: 1.0 },
                 ],
-            )),
-            surface: Surface::Textured(0),
-            color: V3 {
-                x: 1.0,
-                y: 1.0,
-                z: 1.0,
+            ))),
+            material: Material {
+                diffuse: Channel::textured(
+                    V3 {
+                        x: 1.0,
+                        y: 1.0,
+                        z: 1.0,
+                    },
+                    0,
+                ),
+                shininess: 40.0,
+                ..Material::default()
             },
-            shininess: 40.0,
-        });
-
-        // cat triangle 2
-        objects.push(Object2 {
-            shape: Shape::Triangle(Triangle::new_with_uv(
-                [
-                    cat_triangle_vertices[0],
-                    cat_triangle_vertices[2],
-                    cat_triangle_vertices[3],
-                ],
+        })
+        .with_geometry(Geometry {
+            shape: Arc::new(Shape::Triangle(Triangle::new_with_uv(
+                [positions[0], positions[2], positions[3]],
                 [
                     V2 { x: 0.0, y: 0.0 },
                     V2 { x: 1.0, y: 1.0 },
                     V2 { x: 1.0, y: 0.0 },
                 ],
-            )),
-            surface: Surface::Textured(0),
-            color: V3 {
-                x: 1.0,
-                y: 1.0,
-                z: 1.0,
+            ))),
+            material: Material {
+                diffuse: Channel::textured(
+                    V3 {
+                        x: 1.0,
+                        y: 1.0,
+                        z: 1.0,
+                    },
+                    0,
+                ),
+                shininess: 40.0,
+                ..Material::default()
             },
-            shininess: 40.0,
-        });
+        })
+}
 
-        let carpet_triangle_vertices = [
-            transform(V3 {
-                x: 0.0,
-                y: 400.0,
-                z: -0.001,
-            }),
-            transform(V3 {
-                x: 400.0,
-                y: 0.0,
-                z: -0.001,
-            }),
-            transform(V3 {
-                x: 0.0,
-                y: -400.0,
-                z: -0.001,
-            }),
-            transform(V3 {
-                x: -400.0,
-                y: 0.0,
-                z: -0.001,
-            }),
-        ];
-
-        let carpet_wrap_factor = 20.0;
-
-        // carpet triangle 1
-        objects.push(Object2 {
-            shape: Shape::Triangle(Triangle::new_with_uv(
-                [
-                    carpet_triangle_vertices[0],
-                    carpet_triangle_vertices[1],
-                    carpet_triangle_vertices[2],
-                ],
+// perforated metal screen standing behind the right of the table, `grille` the texture
+// ID of its color and holes
+fn make_screen(grille: usize) -> Node {
+    let positions = [
+        V3::new(25.0, 4.0, 0.0),
+        V3::new(9.0, 20.0, 0.0),
+        V3::new(9.0, 20.0, 20.0),
+        V3::new(25.0, 4.0, 20.0),
+    ];
+    let uvs = [
+        V2::new(0.0, 0.0),
+        V2::new(2.0, 0.0),
+        V2::new(2.0, 2.5),
+        V2::new(0.0, 2.5),
+    ];
+    let material = Material {
+        diffuse: Channel::textured(V3::new(1.0, 1.0, 1.0), grille),
+        shininess: 60.0,
+        alpha_mask: Some((grille, 0.5)),
+        ..Material::default()
+    };
+
+    Node::new("screen")
+        .with_geometry(Geometry {
+            shape: Arc::new(Shape::Triangle(Triangle::new_with_uv(
+                [positions[0], positions[1], positions[2]],
+                [uvs[0], uvs[1], uvs[2]],
+            ))),
+            material,
+        })
+        .with_geometry(Geometry {
+            shape: Arc::new(Shape::Triangle(Triangle::new_with_uv(
+                [positions[0], positions[2], positions[3]],
+                [uvs[0], uvs[2], uvs[3]],
+            ))),
+            material,
+        })
+}
+
+// row of small spheres along the front of the table, one for each procedural texture
+fn make_swatches(textures: &mut Vec<Texture>) -> Node {
+    let swatches = vec![
+        Texture::Checker {
+            scale: 8.0,
+            even: V3::new(0.9, 0.9, 0.9),
+            odd: V3::new(0.1, 0.1, 0.1),
+        },
+        Texture::Gradient {
+            from: V3::new(0.9, 0.2, 0.1),
+            to: V3::new(0.1, 0.3, 0.9),
+        },
+        Texture::Noise {
+            scale: 2.0,
+            color: V3::new(0.8, 0.8, 0.3),
+        },
+        Texture::Turbulence {
+            scale: 1.5,
+            octaves: 6,
+            color: V3::new(0.3, 0.8, 0.8),
+        },
+        Texture::Marble {
+            scale: 2.0,
+            turbulence: 4.0,
+            base: V3::new(0.9, 0.9, 0.85),
+            vein: V3::new(0.2, 0.2, 0.25),
+        },
+        Texture::Wood {
+            scale: 3.0,
+            turbulence: 0.5,
+            light: V3::new(0.8, 0.55, 0.3),
+            dark: V3::new(0.45, 0.25, 0.1),
+        },
+    ];
+
+    // from near the left corner of the table to near the right, just in front of it
+    let (start, end) = (V3::new(-20.0, -5.5, 2.0), V3::new(-5.5, -20.0, 2.0));
+    let count = swatches.len();
+
+    swatches
+        .into_iter()
+        .enumerate()
+        .fold(Node::new("swatches"), |node, (i, texture)| {
+            textures.push(texture);
+            let position = start + (end - start) * (i as f32 / (count - 1) as f32);
+
+            node.with_child(
+                // solid textures are looked up around the origin of the swatch
+                Node::new("swatch")
+                    .with_transform(M4::from_translation(position))
+                    .with_geometry(Geometry {
+                        shape: Arc::new(Shape::Sphere(Sphere {
+                            center: V3::zero(),
+                            radius: 2.0,
+                            velocity: V3::zero(),
+                        })),
+                        material: Material {
+                            diffuse: Channel::textured(V3::new(1.0, 1.0, 1.0), textures.len() - 1),
+                            shininess: 40.0,
+                            ..Material::default()
+                        },
+                    }),
+            )
+        })
+}
+
+// `normal_map` is the texture ID of the carpet's normal map
+fn make_carpet(normal_map: usize) -> Node {
+    let positions = [
+        V3 {
+            x: 0.0,
+            y: 400.0,
+            z: -0.001,
+        },
+        V3 {
+            x: 400.0,
+            y: 0.0,
+            z: -0.001,
+        },
+        V3 {
+            x: 0.0,
+            y: -400.0,
+            z: -0.001,
+        },
+        V3 {
+            x: -400.0,
+            y: 0.0,
+            z: -0.001,
+        },
+    ];
+
+    let carpet_wrap_factor = 20.0;
+
+    Node::new("carpet")
+        .with_geometry(Geometry {
+            shape: Arc::new(Shape::Triangle(Triangle::new_with_uv(
+                [positions[0], positions[1], positions[2]],
                 [
                     V2 { x: 0.0, y: 0.0 },
                     V2 {
@@ -255,24 +503,24 @@ impl Scene {
                         y: carpet_wrap_factor,
                     },
                 ],
-            )),
-            surface: Surface::Textured(1),
-            color: V3 {
-                x: 0.4,
-                y: 0.1,
-                z: 0.05,
+            ))),
+            material: Material {
+                diffuse: Channel::textured(
+                    V3 {
+                        x: 0.4,
+                        y: 0.1,
+                        z: 0.05,
+                    },
+                    1,
+                ),
+                shininess: 0.0,
+                bump: Bump::NormalMap(normal_map),
+                ..Material::default()
             },
-            shininess: 0.0,
-        });
-
-        // carpet triangle 2
-        objects.push(Object2 {
-            shape: Shape::Triangle(Triangle::new_with_uv(
-                [
-                    carpet_triangle_vertices[0],
-                    carpet_triangle_vertices[2],
-                    carpet_triangle_vertices[3],
-                ],
+        })
+        .with_geometry(Geometry {
+            shape: Arc::new(Shape::Triangle(Triangle::new_with_uv(
+                [positions[0], positions[2], positions[3]],
                 [
                     V2 { x: 0.0, y: 0.0 },
                     V2 {
@@ -284,158 +532,21 @@ impl Scene {
                         y: 0.0,
                     },
                 ],
-            )),
-            surface: Surface::Textured(1),
-            color: V3 {
-                x: 0.4,
-                y: 0.1,
-                z: 0.05,
-            },
-            shininess: 0.0,
-        });
-
-        let tetrahedron_pos = V3 {
-            x: -3.0,
-            y: 10.0,
-            z: 3.0,
-        };
-
-        let tetrahedron_rotation = Quaternion::from_axis_angle(V3::unit_z(), Deg(60.0));
-
-        let tetrahedron_size = 4.0;
-
-        let tetrahedron_vertices = [
-            transform(
-                tetrahedron_pos
-                    + tetrahedron_rotation.rotate_vector(
-                        V3 {
-                            x: 1.0,
-                            y: 0.0,
-                            z: 1.0 / 2.0_f32.sqrt(),
-                        } * tetrahedron_size,
-                    ),
-            ),
-            transform(
-                tetrahedron_pos
-                    + tetrahedron_rotation.rotate_vector(
-                        V3 {
-                            x: -1.0,
-                            y: 0.0,
-                            z: 1.0 / 2.0_f32.sqrt(),
-                        } * tetrahedron_size,
-                    ),
-            ),
-            transform(
-                tetrahedron_pos
-                    + tetrahedron_rotation.rotate_vector(
-                        V3 {
-                            x: 0.0,
-                            y: 1.0,
-                            z: -1.0 / 2.0_f32.sqrt(),
-                        } * tetrahedron_size,
-                    ),
-            ),
-            transform(
-                tetrahedron_pos
-                    + tetrahedron_rotation.rotate_vector(
-                        V3 {
-                            x: 0.0,
-                            y: -1.0,
-                            z: -1.0 / 2.0_f32.sqrt(),
-                        } * tetrahedron_size,
-                    ),
-            ),
-        ];
-
-        objects.push(Object2 {
-            shape: Shape::Triangle(Triangle::new([
-                tetrahedron_vertices[0],
-                tetrahedron_vertices[1],
-                tetrahedron_vertices[2],
-            ])),
-            surface: Surface::Diffuse,
-            color: V3 {
-                x: 0.2,
-                y: 0.7,
-                z: 0.4,
-            },
-            shininess: 10.0,
-        });
-
-        objects.push(Object2 {
-            shape: Shape::Triangle(Triangle::new([
-                tetrahedron_vertices[0],
-                tetrahedron_vertices[1],
-                tetrahedron_vertices[3],
-            ])),
-            surface: Surface::Diffuse,
-            color: V3 {
-                x: 0.2,
-                y: 0.7,
-                z: 0.4,
-            },
-            shininess: 10.0,
-        });
-
-        objects.push(Object2 {
-            shape: Shape::Triangle(Triangle::new([
-                tetrahedron_vertices[0],
-                tetrahedron_vertices[2],
-                tetrahedron_vertices[3],
-            ])),
-            surface: Surface::Diffuse,
-            color: V3 {
-                x: 0.2,
-                y: 0.7,
-                z: 0.4,
-            },
-            shininess: 10.0,
-        });
-
-        objects.push(Object2 {
-            shape: Shape::Triangle(Triangle::new([
-                tetrahedron_vertices[1],
-                tetrahedron_vertices[2],
-                tetrahedron_vertices[3],
-            ])),
-            surface: Surface::Diffuse,
-            color: V3 {
-                x: 0.2,
-                y: 0.7,
-                z: 0.4,
-            },
-            shininess: 10.0,
-        });
-
-        let mut lights = vec![
-            Light {
-                position: transform(V3 {
-                    x: -29.0,
-                    y: -10.0,
-                    z: 13.0,
-                }),
-                brightness: 40.0,
-            },
-            Light {
-                position: transform(V3 {
-                    x: 25.0,
-                    y: 19.0,
-                    z: 19.0,
-                }),
-                brightness: 50.0,
-            },
-            Light {
-                position: transform(V3 {
-                    x: 0.0,
-                    y: -29.0,
-                    z: 19.0,
-                }),
-                brightness: 60.0,
+            ))),
+            material: Material {
+                diffuse: Channel::textured(
+                    V3 {
+                        x: 0.4,
+                        y: 0.1,
+                        z: 0.05,
+                    },
+                    1,
+                ),
+                shininess: 0.0,
+                bump: Bump::NormalMap(normal_map),
+                ..Material::default()
             },
-        ];
-
-        Scene { objects, lights }
-    }
+        })
 }
 
 const VERTEX_ORDER: [usize; 14] = [7, 5, 1, 3, 2, 5, 4, 7, 6, 1, 0, 2, 6, 4];
@@ -500,46 +611,66 @@ const CUBE_VERTICES: [V3; 8] = [
     },
 ];
 
-fn make_cube(open_top: bool, color: Color, surface: Surface) -> Vec<Object2> {
-    let mut triangles = Vec::new();
+fn make_cube(open_top: bool) -> Shape {
+    let positions = CUBE_VERTICES
+        .iter()
+        .map(|&vertex| vertex + CUBE)
+        .collect();
+
+    let mut indices = Vec::new();
 
     for i in 0..12 {
         if open_top && (i == 3 || i == 4) {
             continue;
         }
 
-        let flip_normal = if (i == 2 || i == 9) { true } else { false };
+        let flip_normal = i == 2 || i == 9;
 
-        let indices = &VERTEX_ORDER[i..i + 3];
+        let triangle = &VERTEX_ORDER[i..i + 3];
 
         let odd_index = i % 2 == 1;
 
         let odd_index = if flip_normal { !odd_index } else { odd_index };
 
-        let triangle = if odd_index {
-            Triangle::new([
-                transform(CUBE_VERTICES[indices[0]] + CUBE),
-                transform(CUBE_VERTICES[indices[1]] + CUBE),
-                transform(CUBE_VERTICES[indices[2]] + CUBE),
-            ])
+        if odd_index {
+            indices.push([triangle[0] as u32, triangle[1] as u32, triangle[2] as u32]);
         } else {
-            Triangle::new([
-                transform(CUBE_VERTICES[indices[0]] + CUBE),
-                transform(CUBE_VERTICES[indices[2]] + CUBE),
-                transform(CUBE_VERTICES[indices[1]] + CUBE),
-            ])
-        };
-        triangles.push(Object2 {
-            shape: Shape::Triangle(triangle),
-            // color: V3 {
-            //     x: ::rand::random::<f32>() % 1.0,
-            //     y: ::rand::random::<f32>() % 1.0,
-            //     z: ::rand::random::<f32>() % 1.0,
-            // },
-            color,
-            surface,
-            shininess: 20.0,
-        });
+            indices.push([triangle[0] as u32, triangle[2] as u32, triangle[1] as u32]);
+        }
     }
-    triangles
+
+    Shape::Mesh(Mesh::new(positions, None, None, indices))
+}
+
+// regular tetrahedron with edge length 2, centred on the origin
+fn make_tetrahedron() -> Shape {
+    let positions = vec![
+        V3 {
+            x: 1.0,
+            y: 0.0,
+            z: 1.0 / 2.0_f32.sqrt(),
+        },
+        V3 {
+            x: -1.0,
+            y: 0.0,
+            z: 1.0 / 2.0_f32.sqrt(),
+        },
+        V3 {
+            x: 0.0,
+            y: 1.0,
+            z: -1.0 / 2.0_f32.sqrt(),
+        },
+        V3 {
+            x: 0.0,
+            y: -1.0,
+            z: -1.0 / 2.0_f32.sqrt(),
+        },
+    ];
+
+    Shape::Mesh(Mesh::new(
+        positions,
+        None,
+        None,
+        vec![[0, 1, 2], [0, 1, 3], [0, 2, 3], [1, 2, 3]],
+    ))
 }
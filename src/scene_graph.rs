@@ -0,0 +1,119 @@
+use cgmath::prelude::*;
+use cgmath::Point3;
+use material::Material;
+use shapes::*;
+use std::sync::Arc;
+use types::*;
+
+/// Geometry attached to a node, with the material it is drawn with.
+#[derive(Debug, Clone)]
+pub struct Geometry {
+	pub shape: Arc<Shape>,
+	pub material: Material,
+}
+
+/// A named node in the scene graph. Geometry, lights and children are all
+/// positioned relative to the node's local transform.
+#[derive(Debug, Clone)]
+pub struct Node {
+	pub name: String,
+	pub transform: M4,
+	// transform at the end of the frame, if the node moves during it
+	pub motion: Option<M4>,
+	pub geometry: Vec<Geometry>,
+	pub lights: Vec<Light>,
+	pub children: Vec<Node>,
+}
+
+impl Node {
+	pub fn new(name: &str) -> Node {
+		Node {
+			name: name.to_string(),
+			transform: M4::identity(),
+			motion: None,
+			geometry: Vec::new(),
+			lights: Vec::new(),
+			children: Vec::new(),
+		}
+	}
+
+	pub fn with_transform(mut self, transform: M4) -> Node {
+		self.transform = transform;
+		self
+	}
+
+	/// Move from `transform` at the start of the frame to `end` at the end of it.
+	pub fn with_motion(mut self, end: M4) -> Node {
+		self.motion = Some(end);
+		self
+	}
+
+	pub fn with_geometry(mut self, geometry: Geometry) -> Node {
+		self.geometry.push(geometry);
+		self
+	}
+
+	pub fn with_light(mut self, light: Light) -> Node {
+		self.lights.push(light);
+		self
+	}
+
+	pub fn with_child(mut self, child: Node) -> Node {
+		self.children.push(child);
+		self
+	}
+
+	/// Depth-first search for the first node called `name`, including this one.
+	#[allow(dead_code)]
+	pub fn find(&self, name: &str) -> Option<&Node> {
+		if self.name == name {
+			return Some(self);
+		}
+		self.children.iter().filter_map(|child| child.find(name)).next()
+	}
+
+	/// Bake the hierarchy into world space objects and lights for rendering.
+	pub fn flatten(&self) -> (Vec<Object2>, Vec<Light>) {
+		let mut objects = Vec::new();
+		let mut lights = Vec::new();
+		self.flatten_into(M4::identity(), M4::identity(), &mut objects, &mut lights);
+		(objects, lights)
+	}
+
+	// motion is baked by blending the world transforms at either end of the frame, so
+	// rotations down the hierarchy sweep in straight lines rather than arcs
+	fn flatten_into(
+		&self,
+		parent: M4,
+		parent_end: M4,
+		objects: &mut Vec<Object2>,
+		lights: &mut Vec<Light>,
+	) {
+		let world = parent * self.transform;
+		let world_end = parent_end * self.motion.unwrap_or(self.transform);
+
+		objects.extend(self.geometry.iter().map(|geometry| {
+			let instance = if world == world_end {
+				Instance::new(geometry.shape.clone(), world)
+			} else {
+				Instance::moving(geometry.shape.clone(), world, world_end)
+			};
+
+			Object2 {
+				shape: Shape::Instance(instance),
+				material: geometry.material,
+			}
+		}));
+
+		lights.extend(self.lights.iter().map(|light| Light {
+			position: world
+				.transform_point(Point3::from_vec(light.position))
+				.to_vec(),
+			..light.clone()
+		}));
+
+		for child in &self.children {
+			child.flatten_into(world, world_end, objects, lights);
+		}
+	}
+}
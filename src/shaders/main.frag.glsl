@@ -1,4 +1,3 @@
-
 #version 140
 out vec4 f_color;
 in vec2 screen_position;
@@ -8,7 +7,18 @@ uniform vec2 divisions;
 
 uniform sampler2D cells;
 
+// in stops, so each step doubles or halves the brightness
+uniform float exposure;
+
+// whether the cells hold raw depth, shown as the inverse relative to the nearest surface
+uniform bool depth;
+uniform float nearest_depth;
+
 void main() {
-    f_color = texture(cells, screen_position); 
+    vec4 color = texture(cells, screen_position);
+    if (depth) {
+        color = color.r > 0.0 ? vec4(vec3(nearest_depth / color.r), 1.0) : vec4(0.0, 0.0, 0.0, 1.0);
+    }
+    f_color = color * exp2(exposure); 
 }
 
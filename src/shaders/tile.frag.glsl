@@ -0,0 +1,8 @@
+#version 140
+out vec4 f_color;
+
+uniform vec4 color;
+
+void main() {
+    f_color = color;
+}
@@ -0,0 +1,9 @@
+#version 140
+in vec2 position;
+
+// scale and offset placing the image in the window
+uniform vec4 view;
+
+void main() {
+    gl_Position = vec4(position * view.xy + view.zw, 0.0, 1.0);
+}
@@ -0,0 +1,128 @@
+use super::Shape;
+use bvh::Aabb;
+use cgmath::prelude::*;
+use cgmath::Point3;
+use std::sync::Arc;
+use types::*;
+
+/// Shared geometry placed in the scene by an affine transform.
+/// Rays are intersected in object space, so the geometry itself is never copied.
+#[derive(Debug)]
+pub struct Instance {
+	geometry: Arc<Shape>,
+	placement: Placement,
+	// transforms at the start and end of the frame, blended for each ray. None if static
+	motion: Option<(M4, M4)>,
+	// world space bounds, over the whole frame
+	bounds: Aabb,
+}
+
+// an invertible transform, with the matrices intersection needs from it
+#[derive(Debug)]
+struct Placement {
+	inverse: M4,
+	// linear part of the transform, for carrying tangents to world space
+	linear: M3,
+	// inverse transpose of the linear part, for carrying normals back to world space
+	normal_matrix: M3,
+}
+
+impl Placement {
+	// None if `transform` is not invertible
+	fn new(transform: M4) -> Option<Placement> {
+		let inverse = transform.invert()?;
+		let inverse_transpose = inverse.transpose();
+
+		Some(Placement {
+			inverse,
+			linear: M3::from_cols(
+				transform.x.truncate(),
+				transform.y.truncate(),
+				transform.z.truncate(),
+			),
+			normal_matrix: M3::from_cols(
+				inverse_transpose.x.truncate(),
+				inverse_transpose.y.truncate(),
+				inverse_transpose.z.truncate(),
+			),
+		})
+	}
+}
+
+impl Instance {
+	/// Panics if `transform` is not invertible.
+	pub fn new(geometry: Arc<Shape>, transform: M4) -> Instance {
+		let bounds = transformed_bounds(&geometry, transform);
+
+		Instance {
+			geometry,
+			placement: Placement::new(transform).expect("instance transform must be invertible"),
+			motion: None,
+			bounds,
+		}
+	}
+
+	/// Geometry moving from `start` at the start of the frame to `end` at the end,
+	/// blending the matrices linearly in between. Panics if `start` is not invertible.
+	pub fn moving(geometry: Arc<Shape>, start: M4, end: M4) -> Instance {
+		// every point moves in a straight line, so stays within the bounds at either end
+		let bounds = transformed_bounds(&geometry, start)
+			.union(transformed_bounds(&geometry, end));
+
+		Instance {
+			geometry,
+			placement: Placement::new(start).expect("instance transform must be invertible"),
+			motion: Some((start, end)),
+			bounds,
+		}
+	}
+}
+
+fn transformed_bounds(geometry: &Shape, transform: M4) -> Aabb {
+	geometry
+		.bounds()
+		.corners()
+		.iter()
+		.fold(Aabb::empty(), |aabb, corner| {
+			aabb.grow(transform.transform_point(Point3::from_vec(*corner)).to_vec())
+		})
+}
+
+pub fn bounds(instance: &Instance) -> Aabb {
+	instance.bounds
+}
+
+pub fn intersection(instance: &Instance, ray: &Ray) -> Option<HitRecord> {
+	let moved;
+	let placement = match instance.motion {
+		None => &instance.placement,
+		Some((start, end)) => {
+			// a blend that collapses the geometry leaves nothing to hit
+			moved = Placement::new(start * (1.0 - ray.time) + end * ray.time)?;
+			&moved
+		}
+	};
+
+	// the direction is deliberately left unnormalised so that t is the same in both spaces
+	let local_ray = Ray {
+		origin: placement
+			.inverse
+			.transform_point(Point3::from_vec(ray.origin))
+			.to_vec(),
+		direction: placement.inverse.transform_vector(ray.direction),
+		time: ray.time,
+	};
+
+	let local_hit = instance.geometry.intersection(&local_ray)?;
+
+	let geometric_normal = (placement.normal_matrix * local_hit.geometric_normal).normalize();
+
+	let mut hit = HitRecord::new(ray, local_hit.t, geometric_normal, local_hit.uv);
+	hit.shading_normal = (placement.normal_matrix * local_hit.shading_normal).normalize();
+	hit.object_point = local_hit.object_point;
+	hit.object_tangent = local_hit.object_tangent;
+	hit.object_bitangent = local_hit.object_bitangent;
+	hit.tangent = placement.linear * local_hit.tangent;
+	hit.bitangent = placement.linear * local_hit.bitangent;
+	Some(hit)
+}
@@ -0,0 +1,87 @@
+use super::triangle::{interpolate, intersect_vertices, tangents, BARYCENTRIC_UV};
+use bvh::{Aabb, Bvh};
+use cgmath::prelude::*;
+use types::*;
+
+/// Indexed triangle mesh. Vertices are shared between triangles and
+/// intersection goes through a BVH over the triangles.
+#[derive(Debug)]
+pub struct Mesh {
+	positions: Vec<V3>,
+	normals: Option<Vec<V3>>,
+	uvs: Option<Vec<V2>>,
+	indices: Vec<[u32; 3]>,
+	bvh: Bvh,
+}
+
+impl Mesh {
+	/// `normals` and `uvs`, if given, are indexed the same way as `positions`.
+	pub fn new(
+		positions: Vec<V3>,
+		normals: Option<Vec<V3>>,
+		uvs: Option<Vec<V2>>,
+		indices: Vec<[u32; 3]>,
+	) -> Mesh {
+		let bounds = indices
+			.iter()
+			.map(|triangle| {
+				Aabb::from_points(&[
+					positions[triangle[0] as usize],
+					positions[triangle[1] as usize],
+					positions[triangle[2] as usize],
+				])
+			})
+			.collect::<Vec<_>>();
+
+		Mesh {
+			bvh: Bvh::new(&bounds),
+			positions,
+			normals,
+			uvs,
+			indices,
+		}
+	}
+
+	fn gather<T: Copy>(attributes: &[T], triangle: [u32; 3]) -> [T; 3] {
+		[
+			attributes[triangle[0] as usize],
+			attributes[triangle[1] as usize],
+			attributes[triangle[2] as usize],
+		]
+	}
+
+	fn intersect_triangle(&self, index: usize, ray: &Ray) -> Option<HitRecord> {
+		let triangle = self.indices[index];
+		let vertex = Mesh::gather(&self.positions, triangle);
+
+		let (t, barycentric) = intersect_vertices(vertex, ray)?;
+
+		let normal = (vertex[0] - vertex[1])
+			.cross(vertex[0] - vertex[2])
+			.normalize();
+
+		// without texture coordinates, expose the barycentrics themselves
+		let vertex_uv = match self.uvs {
+			None => BARYCENTRIC_UV,
+			Some(ref uvs) => Mesh::gather(uvs, triangle),
+		};
+
+		let mut hit = HitRecord::new(ray, t, normal, interpolate(vertex_uv, barycentric));
+		if let Some((tangent, bitangent)) = tangents(vertex, vertex_uv) {
+			hit.set_tangents(tangent, bitangent);
+		}
+		if let Some(ref normals) = self.normals {
+			hit.shading_normal = interpolate(Mesh::gather(normals, triangle), barycentric).normalize();
+		}
+		Some(hit)
+	}
+}
+
+pub fn bounds(mesh: &Mesh) -> Aabb {
+	mesh.bvh.bounds()
+}
+
+pub fn intersection(mesh: &Mesh, ray: &Ray) -> Option<HitRecord> {
+	mesh.bvh
+		.closest_hit(ray, |index| mesh.intersect_triangle(index, ray))
+}
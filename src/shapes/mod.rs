@@ -1,61 +1,67 @@
+use bvh::Aabb;
+use material::Material;
 use types::*;
 
+mod instance;
+mod mesh;
 mod sphere;
 mod triangle;
 
+pub use self::instance::Instance;
+pub use self::mesh::Mesh;
 pub use self::sphere::Sphere;
 pub use self::triangle::Triangle;
 
-#[derive(Debug, Clone, Copy)]
-pub enum Surface {
-	Diffuse,
-	// Portion of light diffuse vs. reflected
-	Reflective(f32),
-	// Portion of light diffuse vs. refracted and refractive index
-	//Refractive(f32, f32),
-	// Portion of light diffuse vs. (reflected/refracted), portion of light reflected vs. refracted, refractive index
-	//ReflectiveAndRefractive(f32, f32, f32),
-	// Texture ID
-	Textured(usize),
-}
-
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Light {
 	pub position: V3,
 	pub brightness: f32,
+	// size of a spherical light, for soft shadows. 0 is a point light
+	pub radius: f32,
+}
+
+impl Light {
+	/// Point on the light picked by `sample`, in [0, 1]^2.
+	pub fn sample_point(&self, sample: V2) -> V3 {
+		self.position + ::util::sphere_direction(sample) * self.radius
+	}
 }
 
 #[derive(Debug)]
 pub struct Object2 {
-	pub color: Color,
-	pub surface: Surface,
+	pub material: Material,
 	pub shape: Shape,
-	pub shininess: f32,
 }
 
 #[derive(Debug)]
 pub enum Shape {
 	Triangle(Triangle),
 	Sphere(Sphere),
+	Mesh(Mesh),
+	Instance(Instance),
 }
 
-impl Object2 {
-	pub fn closest_intersection(&self, ray: &Ray) -> Option<f32> {
-		match self.shape {
-			Shape::Sphere(ref sphere) => sphere::intersection(&sphere, ray),
-			Shape::Triangle(ref triangle) => triangle::intersection(&triangle, ray),
+impl Shape {
+	pub fn intersection(&self, ray: &Ray) -> Option<HitRecord> {
+		match *self {
+			Shape::Sphere(ref sphere) => sphere::intersection(sphere, ray),
+			Shape::Triangle(ref triangle) => triangle::intersection(triangle, ray),
+			Shape::Mesh(ref mesh) => mesh::intersection(mesh, ray),
+			Shape::Instance(ref instance) => instance::intersection(instance, ray),
 		}
 	}
-	pub fn normal(&self, intersection: V3) -> V3 {
-		match self.shape {
-			Shape::Sphere(ref sphere) => sphere::normal(&sphere, intersection),
-			Shape::Triangle(ref triangle) => triangle::normal(&triangle),
+	pub fn bounds(&self) -> Aabb {
+		match *self {
+			Shape::Sphere(ref sphere) => sphere::bounds(sphere),
+			Shape::Triangle(ref triangle) => triangle::bounds(triangle),
+			Shape::Mesh(ref mesh) => mesh::bounds(mesh),
+			Shape::Instance(ref instance) => instance::bounds(instance),
 		}
 	}
-	pub fn get_texture_coord(&self, intersection: V3) -> V2 {
-		match self.shape {
-			Shape::Sphere(ref sphere) => V2 { x: 0.0, y: 0.0 },
-			Shape::Triangle(ref triangle) => triangle::get_texture_coord(&triangle, intersection),
-		}
+}
+
+impl Object2 {
+	pub fn closest_intersection(&self, ray: &Ray) -> Option<HitRecord> {
+		self.shape.intersection(ray)
 	}
 }
@@ -1,25 +1,42 @@
+use bvh::Aabb;
 use cgmath::prelude::*;
+use std::f32::consts::PI;
 use types::*;
 use util::*;
 
 #[derive(Debug)]
 pub struct Sphere {
+	// center at the start of the frame
 	pub center: V3,
 	pub radius: f32,
+	// distance the center moves in a straight line over the frame
+	pub velocity: V3,
 }
-pub fn normal(sphere: &Sphere, intersection: V3) -> V3 {
-	(intersection - sphere.center).normalize()
+
+impl Sphere {
+	pub fn center_at(&self, time: f32) -> V3 {
+		self.center + self.velocity * time
+	}
+}
+
+pub fn bounds(sphere: &Sphere) -> Aabb {
+	let extent = V3::new(sphere.radius, sphere.radius, sphere.radius);
+	let start = sphere.center_at(0.0);
+	let end = sphere.center_at(1.0);
+	Aabb::from_points(&[start - extent, start + extent, end - extent, end + extent])
 }
 
-pub fn intersection(sphere: &Sphere, ray: &Ray) -> Option<f32> {
+pub fn intersection(sphere: &Sphere, ray: &Ray) -> Option<HitRecord> {
+	let center = sphere.center_at(ray.time);
+
 	// quadratic polynomial from analytic solution
-	let shared_term = ray.origin - sphere.center;
+	let shared_term = ray.origin - center;
 	let a = ray.direction.dot(ray.direction);
 	let b = 2.0 * ray.direction.dot(shared_term);
-	let c = shared_term.dot(shared_term) - sphere.radius;
+	let c = shared_term.dot(shared_term) - sphere.radius * sphere.radius;
 
 	// solve for t (distance along ray) and choose closest root that is greater than 0
-	match solve_quadratic(a, b, c) {
+	let t = match solve_quadratic(a, b, c) {
 		QuadraticRoot::None => None,
 		QuadraticRoot::One(t) => if t > EPSILON {
 			Some(t)
@@ -30,14 +47,41 @@ pub fn intersection(sphere: &Sphere, ray: &Ray) -> Option<f32> {
 			None
 		} else if t2 < EPSILON {
 			Some(t1)
-		} else if t1 < EPSILON {
+		} else if t1 < EPSILON || t2 < t1 {
 			Some(t2)
 		} else {
-			if t1 < t2 {
-				Some(t1)
-			} else {
-				Some(t2)
-			}
+			Some(t1)
 		},
+	}?;
+
+	let normal = (ray.at(t) - center).normalize();
+
+	let mut hit = HitRecord::new(ray, t, normal, texture_coord(normal));
+	// solid textures move with the sphere
+	hit.object_point = hit.point - sphere.velocity * ray.time;
+
+	// derivatives of the latitude/longitude mapping, undefined at the poles
+	let around = V3 {
+		x: normal.z,
+		y: 0.0,
+		z: -normal.x,
+	};
+	if around.magnitude2() > EPSILON * EPSILON {
+		hit.set_tangents(
+			around * (2.0 * PI * sphere.radius),
+			normal.cross(around).normalize() * (PI * sphere.radius),
+		);
+	}
+	Some(hit)
+}
+
+// latitude/longitude mapping of a point on the unit sphere
+fn texture_coord(normal: V3) -> V2 {
+	let phi = normal.z.atan2(normal.x);
+	let theta = normal.y.clamp(-1.0, 1.0).asin();
+
+	V2 {
+		x: 1.0 - (phi + PI) / (2.0 * PI),
+		y: (theta + PI / 2.0) / PI,
 	}
 }
@@ -1,3 +1,4 @@
+use bvh::Aabb;
 use cgmath::prelude::*;
 use types::*;
 
@@ -8,6 +9,7 @@ pub struct Triangle {
 }
 
 impl Triangle {
+	#[allow(dead_code)]
 	pub fn new(vertices: [V3; 3]) -> Triangle {
 		Triangle { vertices, uv: None }
 	}
@@ -28,17 +30,15 @@ impl Triangle {
 pub fn normal(triangle: &Triangle) -> V3 {
 	let vertex = triangle.vertices();
 
-	let normal = (vertex[0] - vertex[1])
+	(vertex[0] - vertex[1])
 		.cross(vertex[0] - vertex[2])
-		.normalize();
-
-	normal
+		.normalize()
 }
 
-pub fn intersection(triangle: &Triangle, ray: &Ray) -> Option<f32> {
-	// Möller–Trumbore ray-triangle intersection algorithm
-	let vertex = triangle.vertices();
-
+/// Möller–Trumbore ray-triangle intersection algorithm.
+/// Returns the distance along the ray and the barycentric coordinates (u, v)
+/// of the hit, weighting the second and third vertices respectively.
+pub fn intersect_vertices(vertex: [V3; 3], ray: &Ray) -> Option<(f32, V2)> {
 	let edge_1 = vertex[1] - vertex[0];
 	let edge_2 = vertex[2] - vertex[0];
 	let h = ray.direction.cross(edge_2);
@@ -49,7 +49,7 @@ pub fn intersection(triangle: &Triangle, ray: &Ray) -> Option<f32> {
 	let f = 1.0 / a;
 	let s = ray.origin - vertex[0];
 	let u = f * s.dot(h);
-	if u < 0.0 || u > 1.0 {
+	if !(0.0..=1.0).contains(&u) {
 		return None;
 	}
 	let q = s.cross(edge_1);
@@ -60,36 +60,63 @@ pub fn intersection(triangle: &Triangle, ray: &Ray) -> Option<f32> {
 	// At this stage we can compute t to find out where the intersection point is on the line.
 	let t = f * edge_2.dot(q);
 	if t > EPSILON {
-		return Some(t);
+		Some((t, V2 { x: u, y: v }))
 	} else {
 		// This means that there is a line intersection but not a ray intersection.
+		None
+	}
+}
+
+/// Interpolate per-vertex attributes using barycentric coordinates from `intersect_vertices`.
+pub fn interpolate<T>(attributes: [T; 3], barycentric: V2) -> T
+where
+	T: VectorSpace<Scalar = f32>,
+{
+	attributes[0] * (1.0 - barycentric.x - barycentric.y)
+		+ attributes[1] * barycentric.x
+		+ attributes[2] * barycentric.y
+}
+
+/// Derivatives of position with respect to the texture coordinates (dP/du, dP/dv),
+/// or None if the texture coordinates are degenerate.
+pub fn tangents(vertex: [V3; 3], uv: [V2; 3]) -> Option<(V3, V3)> {
+	let edge_1 = vertex[1] - vertex[0];
+	let edge_2 = vertex[2] - vertex[0];
+	let delta_1 = uv[1] - uv[0];
+	let delta_2 = uv[2] - uv[0];
+
+	let determinant = delta_1.x * delta_2.y - delta_2.x * delta_1.y;
+	if determinant.abs() < 1e-12 {
 		return None;
 	}
+	let r = 1.0 / determinant;
+
+	Some((
+		(edge_1 * delta_2.y - edge_2 * delta_1.y) * r,
+		(edge_2 * delta_1.x - edge_1 * delta_2.x) * r,
+	))
 }
 
-pub fn get_texture_coord(triangle: &Triangle, intersection: V3) -> V2 {
-	let [vertex_1, vertex_2, vertex_3] = triangle.vertices();
+// texture coordinates used when a triangle has none, matching the barycentrics
+pub const BARYCENTRIC_UV: [V2; 3] = [
+	V2 { x: 0.0, y: 0.0 },
+	V2 { x: 1.0, y: 0.0 },
+	V2 { x: 0.0, y: 1.0 },
+];
 
-	let uv = match triangle.uv() {
-		None => return V2 { x: 0.0, y: 0.0 },
-		Some(uv) => uv,
-	};
+pub fn bounds(triangle: &Triangle) -> Aabb {
+	Aabb::from_points(triangle.vertices())
+}
 
-	let f1 = vertex_1 - intersection;
-	let f2 = vertex_2 - intersection;
-	let f3 = vertex_3 - intersection;
-	// calculate the areas and factors (order of parameters doesn't matter):
-	let area = (vertex_1 - vertex_2).cross(vertex_1 - vertex_3).magnitude(); // main triangle area a
-	let area_1 = f2.cross(f3).magnitude() / area; // p1's triangle area / a
-	let area_2 = f3.cross(f1).magnitude() / area; // p2's triangle area / a
-	let area_3 = f1.cross(f2).magnitude() / area; // p3's triangle area / a
-											   // find the uv corresponding to point f (uv1/uv2/uv3 are associated to p1/p2/p3):
-	let coord = uv[0] * area_1 + uv[1] * area_2 + uv[2] * area_3;
+pub fn intersection(triangle: &Triangle, ray: &Ray) -> Option<HitRecord> {
+	let (t, barycentric) = intersect_vertices(*triangle.vertices(), ray)?;
 
-	let coord = V2 {
-		x: coord.x % 0.999,
-		y: coord.y % 0.999,
-	};
+	// without texture coordinates, expose the barycentrics themselves
+	let vertex_uv = triangle.uv().unwrap_or(BARYCENTRIC_UV);
 
-	coord
+	let mut hit = HitRecord::new(ray, t, normal(triangle), interpolate(vertex_uv, barycentric));
+	if let Some((tangent, bitangent)) = tangents(*triangle.vertices(), vertex_uv) {
+		hit.set_tangents(tangent, bitangent);
+	}
+	Some(hit)
 }
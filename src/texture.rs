@@ -0,0 +1,182 @@
+use cgmath::prelude::*;
+use image::{DynamicImage, GenericImage, ImageBuffer, Rgb, Rgba};
+use noise;
+use types::*;
+
+/// Colour source for material channels, normal maps and alpha masks.
+/// Image and 2D patterns are looked up by texture coordinate, solid patterns
+/// by the hit point in object space.
+pub enum Texture {
+	Image(DynamicImage),
+	// squares of side `1 / scale` in texture space
+	Checker {
+		scale: f32,
+		even: Color,
+		odd: Color,
+	},
+	// linear blend across the u texture coordinate
+	Gradient {
+		from: Color,
+		to: Color,
+	},
+	Noise {
+		scale: f32,
+		color: Color,
+	},
+	Turbulence {
+		scale: f32,
+		octaves: u32,
+		color: Color,
+	},
+	// veins running along the x axis, distorted by `turbulence`
+	Marble {
+		scale: f32,
+		turbulence: f32,
+		base: Color,
+		vein: Color,
+	},
+	// concentric rings around the z axis
+	Wood {
+		scale: f32,
+		turbulence: f32,
+		light: Color,
+		dark: Color,
+	},
+}
+
+const TURBULENCE_OCTAVES: u32 = 6;
+
+fn mix(a: Color, b: Color, t: f32) -> Color {
+	a * (1.0 - t) + b * t
+}
+
+impl Texture {
+	/// Opacity, from the alpha channel of images. Procedural textures are opaque.
+	pub fn alpha(&self, uv: V2) -> f32 {
+		match *self {
+			Texture::Image(ref image) => image_pixel(image, uv).data[3] as f32 / 255.0,
+			_ => 1.0,
+		}
+	}
+
+	/// Raw texel values without gamma decoding, for normal and height maps.
+	pub fn data(&self, uv: V2, point: V3) -> V3 {
+		match *self {
+			Texture::Image(ref image) => image_data(image, uv),
+			_ => self.value(uv, point),
+		}
+	}
+
+	/// Linear colour at a texture coordinate / object space point.
+	pub fn value(&self, uv: V2, point: V3) -> Color {
+		match *self {
+			Texture::Image(ref image) => {
+				// images are stored with gamma, square to get back to roughly linear
+				let color = image_data(image, uv);
+				V3 {
+					x: color.x * color.x,
+					y: color.y * color.y,
+					z: color.z * color.z,
+				}
+			}
+			Texture::Checker { scale, even, odd } => {
+				let parity = (uv.x * scale).floor() as i32 + (uv.y * scale).floor() as i32;
+				if parity % 2 == 0 {
+					even
+				} else {
+					odd
+				}
+			}
+			Texture::Gradient { from, to } => mix(from, to, uv.x.clamp(0.0, 1.0)),
+			Texture::Noise { scale, color } => {
+				color * (0.5 * (1.0 + noise::perlin(point * scale)))
+			}
+			Texture::Turbulence {
+				scale,
+				octaves,
+				color,
+			} => color * noise::turbulence(point * scale, octaves).min(1.0),
+			Texture::Marble {
+				scale,
+				turbulence,
+				base,
+				vein,
+			} => {
+				let distortion = turbulence * noise::turbulence(point * scale, TURBULENCE_OCTAVES);
+				let t = 0.5 * (1.0 + (point.x * scale + distortion).sin());
+				mix(vein, base, t)
+			}
+			Texture::Wood {
+				scale,
+				turbulence,
+				light,
+				dark,
+			} => {
+				let distortion = turbulence * noise::fbm(point * scale, TURBULENCE_OCTAVES);
+				let rings = (point.x * point.x + point.y * point.y).sqrt() * scale + distortion;
+				mix(light, dark, rings - rings.floor())
+			}
+		}
+	}
+}
+
+fn image_pixel(image: &DynamicImage, uv: V2) -> Rgba<u8> {
+	let (width, height) = image.dimensions();
+
+	// wrap so the texture tiles, negative coordinates included. Rounding can carry a
+	// coordinate just under 1 onto the far edge, so keep the index inside the image
+	let (u, v) = (uv.x.rem_euclid(1.0), uv.y.rem_euclid(1.0));
+
+	let pixel_x = ((width as f32 * u).floor() as u32).min(width - 1);
+	let pixel_y = ((height as f32 * v).floor() as u32).min(height - 1);
+
+	image.get_pixel(pixel_x, pixel_y)
+}
+
+fn image_data(image: &DynamicImage, uv: V2) -> V3 {
+	let pixel = image_pixel(image, uv);
+
+	V3 {
+		x: pixel.data[0] as f32 / 256.0,
+		y: pixel.data[1] as f32 / 256.0,
+		z: pixel.data[2] as f32 / 256.0,
+	}
+}
+
+/// Tangent space normal map of `image`, reading its brightness as height. `strength` is
+/// the slope of a step from black to white between neighbouring pixels.
+pub fn normal_map_from_heights(image: &DynamicImage, strength: f32) -> DynamicImage {
+	let (width, height) = image.dimensions();
+	// wrapping at the edges, so the normal map tiles like the image
+	let height_at = |x: i64, y: i64| {
+		let pixel = image.get_pixel(
+			x.rem_euclid(width as i64) as u32,
+			y.rem_euclid(height as i64) as u32,
+		);
+		(pixel.data[0] as f32 + pixel.data[1] as f32 + pixel.data[2] as f32) / (3.0 * 255.0)
+	};
+
+	DynamicImage::ImageRgb8(ImageBuffer::from_fn(width, height, |x, y| {
+		let (x, y) = (x as i64, y as i64);
+		// central differences, with u along x and v along y
+		let slope_u = (height_at(x + 1, y) - height_at(x - 1, y)) / 2.0;
+		let slope_v = (height_at(x, y + 1) - height_at(x, y - 1)) / 2.0;
+		let normal = V3::new(-slope_u * strength, -slope_v * strength, 1.0).normalize();
+
+		let encode = |value: f32| ((value * 0.5 + 0.5) * 255.0).round() as u8;
+		Rgb([encode(normal.x), encode(normal.y), encode(normal.z)])
+	}))
+}
+
+/// Square `size` pixel image of `color`, with a grid of `holes` by `holes` round holes
+/// cut out of its alpha channel, e.g. for a perforated screen.
+pub fn perforated_image(size: u32, holes: u32, color: Rgba<u8>) -> DynamicImage {
+	let cell = size as f32 / holes as f32;
+	DynamicImage::ImageRgba8(ImageBuffer::from_fn(size, size, |x, y| {
+		// from the centre of the hole in this cell, in cells
+		let dx = (x as f32 + 0.5) / cell % 1.0 - 0.5;
+		let dy = (y as f32 + 0.5) / cell % 1.0 - 0.5;
+		let alpha = if dx * dx + dy * dy < 0.35 * 0.35 { 0 } else { 255 };
+		Rgba([color.data[0], color.data[1], color.data[2], alpha])
+	}))
+}
@@ -0,0 +1,117 @@
+use rand::{thread_rng, Rng};
+use std::cmp::Ordering;
+use std::sync::{Arc, Mutex};
+
+/// Rectangle of pixels rendered together, for cache locality.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tile {
+	pub x: usize,
+	pub y: usize,
+	pub width: usize,
+	pub height: usize,
+}
+
+impl Tile {
+	/// Indices of the pixels in the tile, row by row, in an image `image_width` pixels wide.
+	pub fn pixels(&self, image_width: usize) -> impl Iterator<Item = usize> {
+		let tile = *self;
+		(tile.y..tile.y + tile.height)
+			.flat_map(move |y| (tile.x..tile.x + tile.width).map(move |x| y * image_width + x))
+	}
+}
+
+/// Order tiles are handed out to render threads in.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub enum TileOrder {
+	// rows from the bottom of the image
+	Scanline,
+	// rings outwards from the centre of the image, where the subject usually is
+	Spiral,
+	// along a Hilbert curve padded out to a power of two square, so consecutive tiles are
+	// mostly neighbours, jumping only where the curve leaves the image and comes back
+	Hilbert,
+	Random,
+}
+
+/// Split a `width` by `height` image into tiles of at most `size` pixels square, in `order`.
+pub fn make_tiles(width: usize, height: usize, size: usize, order: TileOrder) -> Vec<Tile> {
+	let columns = width.div_ceil(size);
+	let rows = height.div_ceil(size);
+
+	let mut tiles: Vec<Tile> = (0..rows)
+		.flat_map(|row| (0..columns).map(move |column| (column, row)))
+		.map(|(column, row)| Tile {
+			x: column * size,
+			y: row * size,
+			width: size.min(width - column * size),
+			height: size.min(height - row * size),
+		})
+		.collect();
+
+	match order {
+		TileOrder::Scanline => {}
+		TileOrder::Spiral => {
+			let spiral_position = |tile: &Tile| {
+				let dx = (tile.x + tile.width / 2) as f32 - width as f32 / 2.0;
+				let dy = (tile.y + tile.height / 2) as f32 - height as f32 / 2.0;
+				let ring = (dx.abs().max(dy.abs()) / size as f32).round();
+				(ring, dy.atan2(dx))
+			};
+			tiles.sort_by(|a, b| {
+				spiral_position(a)
+					.partial_cmp(&spiral_position(b))
+					.unwrap_or(Ordering::Equal)
+			});
+		}
+		TileOrder::Hilbert => {
+			let side = columns.max(rows).next_power_of_two();
+			tiles.sort_by_key(|tile| hilbert_index(side, tile.x / size, tile.y / size));
+		}
+		TileOrder::Random => thread_rng().shuffle(&mut tiles),
+	}
+
+	tiles
+}
+
+// distance along the Hilbert curve filling a `side` by `side` grid, `side` a power of two
+fn hilbert_index(side: usize, mut x: usize, mut y: usize) -> usize {
+	let mut index = 0;
+	let mut scale = side / 2;
+	while scale > 0 {
+		let rx = (x & scale > 0) as usize;
+		let ry = (y & scale > 0) as usize;
+		index += scale * scale * ((3 * rx) ^ ry);
+
+		// rotate the quadrant so the curve inside it joins up with its neighbours
+		if ry == 0 {
+			if rx == 1 {
+				x = side - 1 - x;
+				y = side - 1 - y;
+			}
+			::std::mem::swap(&mut x, &mut y);
+		}
+		scale /= 2;
+	}
+	index
+}
+
+/// Tiles currently being rendered, shared with the viewer so it can highlight them.
+#[derive(Debug, Clone, Default)]
+pub struct ActiveTiles {
+	tiles: Arc<Mutex<Vec<Tile>>>,
+}
+
+impl ActiveTiles {
+	pub fn start(&self, tile: Tile) {
+		self.tiles.lock().unwrap().push(tile);
+	}
+
+	pub fn finish(&self, tile: Tile) {
+		self.tiles.lock().unwrap().retain(|active| *active != tile);
+	}
+
+	pub fn to_vec(&self) -> Vec<Tile> {
+		self.tiles.lock().unwrap().clone()
+	}
+}
@@ -1,12 +1,66 @@
-use cgmath::{Vector2, Vector3};
+use cgmath::prelude::*;
+use camera::{CameraSettings, ProjectionKind};
+use cgmath::{Matrix3, Matrix4, Vector2, Vector3};
+use filter::Filter;
+use output::ExrPixelType;
+use sampler::SamplerKind;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
+use tiles::{make_tiles, Tile, TileOrder};
+use util::{AtomicF32, AtomicV3, V3Extensions};
 use std::vec::Vec;
 pub const EPSILON: f32 = 0.001;
 
+// size of the render, in pixels
 pub const CELLS_WIDE: usize = 1920;
 pub const CELLS_HIGH: usize = 1080;
+// render at the window's size instead, starting again whenever the window is resized, rather
+// than scaling the CELLS_WIDE by CELLS_HIGH image to fit it
+pub const RENDER_AT_WINDOW_SIZE: bool = false;
 
-pub const ANTIALIASING_DIV: usize = 4;
+// refine the whole image a sample per pixel at a time, rather than finishing each pixel in turn
+pub const PROGRESSIVE: bool = true;
+
+// side of the blocks of pixels sharing a sample in the quick preview at the start of a render
+pub const PREVIEW_SCALE: usize = 8;
+
+// pixels rendered together, handed out to threads in order
+pub const TILE_SIZE: usize = 32;
+pub const TILE_ORDER: TileOrder = TileOrder::Spiral;
+
+// pixels take samples in batches, stopping once they reach the noise threshold or the maximum
+pub const MIN_SAMPLES_PER_PIXEL: usize = 8;
+pub const MAX_SAMPLES_PER_PIXEL: usize = 64;
+pub const SAMPLE_BATCH: usize = 4;
+// standard error of a pixel's luminance, relative to its brightness
+pub const NOISE_THRESHOLD: f32 = 0.02;
+pub const SAMPLER: SamplerKind = SamplerKind::Sobol;
+pub const FILTER: Filter = Filter::Mitchell {
+	radius: 2.0,
+	b: 1.0 / 3.0,
+	c: 1.0 / 3.0,
+};
+
+// projection of the scene camera: perspective, orthographic, fisheye or equirectangular,
+// and whether it renders a stereo pair side by side, top and bottom or as an anaglyph
+pub const CAMERA: CameraSettings = CameraSettings {
+	projection: ProjectionKind::Perspective {
+		// a pinhole; open the aperture up to blur everything off the focal plane
+		aperture_radius: 0.0,
+		focus_distance: 30.0,
+		aperture_blades: 6,
+	},
+	// e.g. Some(Stereo { interocular_distance: 0.65, convergence_distance: 30.0,
+	// layout: StereoLayout::Anaglyph }) for red/cyan glasses
+	stereo: None,
+	// open for the whole frame, so moving objects blur. Close it at 0 to freeze them
+	shutter_open: 0.0,
+	shutter_close: 1.0,
+};
+
+// precision of the color and normal channels in saved OpenEXR files. Half is half the size,
+// depth is always full float
+pub const EXR_COLOR_PIXEL_TYPE: ExrPixelType = ExrPixelType::Float;
 
 pub const DEFAULT_COLOR: Color = V3 {
 	x: 0.0,
@@ -24,54 +78,359 @@ pub const MAX_TRACE_DEPTH: u32 = 12;
 
 pub type Color = V3;
 
-use std::cell;
+// side of the blocks of pixels the viewer re-uploads when any pixel in them changes
+pub const DIRTY_BLOCK_SIZE: usize = 64;
 
 #[derive(Clone)]
 pub struct Cells {
 	pub data: Arc<Vec<Cell>>,
+	// in pixels
+	width: usize,
+	height: usize,
+	// blocks of the image changed since the viewer last took them, in scanline order
+	blocks: Arc<Vec<Tile>>,
+	dirty: Arc<Vec<AtomicBool>>,
+	// smallest depth written since the render started, which the viewer shades depth against
+	nearest_depth: Arc<AtomicF32>,
 }
 
-#[derive(Clone)]
+/// One pixel of the framebuffer, written by the render threads while the viewer reads it.
+/// Every value is stored atomically, so a read racing a write can mix old and new
+/// channels for a frame, but never sees a torn float.
 pub struct Cell {
-	inner: cell::Cell<Color>,
+	color: AtomicV3,
+	statistics: AtomicStatistics,
+	surface: AtomicSurface,
 }
 
 impl Cell {
 	pub fn new(color: Color) -> Cell {
 		Cell {
-			inner: cell::Cell::new(color),
+			color: AtomicV3::new(color),
+			statistics: AtomicStatistics::default(),
+			surface: AtomicSurface::default(),
 		}
 	}
 
 	pub fn get_content(&self) -> Color {
-		self.inner.get()
+		self.color.load()
 	}
 
 	pub fn set_content(&self, color: Color) {
-		self.inner.set(color)
+		self.color.store(color)
+	}
+
+	pub fn get_statistics(&self) -> SampleStatistics {
+		SampleStatistics {
+			count: self.statistics.count.load(Ordering::Acquire),
+			mean: self.statistics.mean.load(),
+			squared_deviations: self.statistics.squared_deviations.load(),
+		}
+	}
+
+	pub fn set_statistics(&self, statistics: SampleStatistics) {
+		self.statistics.mean.store(statistics.mean);
+		self.statistics
+			.squared_deviations
+			.store(statistics.squared_deviations);
+		self.statistics.count.store(statistics.count, Ordering::Release);
+	}
+
+	pub fn get_surface(&self) -> SurfaceChannels {
+		SurfaceChannels {
+			normal: self.surface.normal.load(),
+			depth: self.surface.depth.load(),
+			albedo: self.surface.albedo.load(),
+		}
+	}
+
+	pub fn set_surface(&self, surface: SurfaceChannels) {
+		self.surface.normal.store(surface.normal);
+		self.surface.depth.store(surface.depth);
+		self.surface.albedo.store(surface.albedo);
+	}
+}
+
+#[derive(Default)]
+struct AtomicStatistics {
+	count: AtomicU32,
+	mean: AtomicF32,
+	squared_deviations: AtomicF32,
+}
+
+#[derive(Default)]
+struct AtomicSurface {
+	normal: AtomicV3,
+	depth: AtomicF32,
+	albedo: AtomicV3,
+}
+
+/// What the camera rays through a pixel first hit, averaged over its samples.
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceChannels {
+	pub normal: V3,
+	// distance from the camera, 0 where the rays missed everything
+	pub depth: f32,
+	pub albedo: Color,
+}
+
+impl Default for SurfaceChannels {
+	fn default() -> SurfaceChannels {
+		SurfaceChannels {
+			normal: V3::zero(),
+			depth: 0.0,
+			albedo: V3::zero(),
+		}
 	}
 }
 
-unsafe impl Sync for Cell {}
+impl SurfaceChannels {
+	/// Fold in `sample`, the `count`th sample taken.
+	pub fn accumulate(&mut self, sample: SurfaceChannels, count: usize) {
+		let weight = 1.0 / count as f32;
+		self.normal += (sample.normal - self.normal) * weight;
+		self.depth += (sample.depth - self.depth) * weight;
+		self.albedo += (sample.albedo - self.albedo) * weight;
+	}
+}
+
+/// Which of a pixel's values the viewer shows and saves.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplayChannel {
+	// the rendered image
+	Beauty,
+	// remapped from [-1, 1] to [0, 1]
+	Normals,
+	// inverse depth, so the nearest surface is white and the distance fades to black. The
+	// viewer is given the raw depth and shades it on the GPU
+	Depth,
+	Albedo,
+}
+
+/// Running mean and variance of the luminance of a pixel's samples, by Welford's method.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SampleStatistics {
+	pub count: u32,
+	pub mean: f32,
+	// sum of squared differences from the mean
+	squared_deviations: f32,
+}
+
+impl SampleStatistics {
+	pub fn add(&mut self, value: f32) {
+		self.count += 1;
+		let delta = value - self.mean;
+		self.mean += delta / self.count as f32;
+		self.squared_deviations += delta * (value - self.mean);
+	}
+
+	pub fn variance(&self) -> f32 {
+		if self.count < 2 {
+			0.0
+		} else {
+			self.squared_deviations / (self.count - 1) as f32
+		}
+	}
+
+	/// Standard error of the mean relative to the mean, offset so that near black
+	/// pixels aren't held to an impossible standard.
+	pub fn relative_error(&self) -> f32 {
+		if self.count == 0 {
+			return f32::INFINITY;
+		}
+		(self.variance() / self.count as f32).sqrt() / (self.mean + 0.1)
+	}
+}
 
 impl Cells {
-	pub fn to_vec(self) -> Vec<f32> {
-		let mut v = Vec::with_capacity(self.data.len());
-		for cell in self.data.iter() {
-			let vector_cell = cell.get_content();
+	/// A `width` by `height` framebuffer of `color`, all of it dirty.
+	pub fn new(width: usize, height: usize, color: Color) -> Cells {
+		let blocks = make_tiles(width, height, DIRTY_BLOCK_SIZE, TileOrder::Scanline);
+		Cells {
+			data: Arc::new((0..width * height).map(|_| Cell::new(color)).collect()),
+			width,
+			height,
+			dirty: Arc::new(blocks.iter().map(|_| AtomicBool::new(true)).collect()),
+			blocks: Arc::new(blocks),
+			nearest_depth: Arc::new(AtomicF32::new(f32::INFINITY)),
+		}
+	}
+
+	pub fn width(&self) -> usize {
+		self.width
+	}
+
+	pub fn height(&self) -> usize {
+		self.height
+	}
+
+	pub fn set_content(&self, index: usize, color: Color) {
+		self.data[index].set_content(color);
+		self.mark_dirty(index);
+	}
+
+	pub fn set_surface(&self, index: usize, surface: SurfaceChannels) {
+		self.data[index].set_surface(surface);
+		if surface.depth > 0.0 {
+			self.nearest_depth.min(surface.depth);
+		}
+		self.mark_dirty(index);
+	}
+
+	fn mark_dirty(&self, index: usize) {
+		let blocks_wide = self.width.div_ceil(DIRTY_BLOCK_SIZE);
+		let (x, y) = (index % self.width, index / self.width);
+		let block = y / DIRTY_BLOCK_SIZE * blocks_wide + x / DIRTY_BLOCK_SIZE;
+		// released after the pixel's stores, so whoever takes the flag reads the new values
+		self.dirty[block].store(true, Ordering::Release);
+	}
+
+	/// Mark the whole image as changed, e.g. when the viewer switches channel.
+	pub fn mark_all_dirty(&self) {
+		for dirty in self.dirty.iter() {
+			dirty.store(true, Ordering::Release);
+		}
+	}
+
+	/// Blocks with pixels changed since the last call, clearing their flags.
+	pub fn take_dirty(&self) -> Vec<Tile> {
+		self.blocks
+			.iter()
+			.zip(self.dirty.iter())
+			.filter(|&(_, dirty)| dirty.swap(false, Ordering::AcqRel))
+			.map(|(&block, _)| block)
+			.collect()
+	}
+
+	/// RGB values of `channel` for every pixel, row by row from the bottom.
+	pub fn to_vec(&self, channel: DisplayChannel) -> Vec<f32> {
+		self.region_to_vec(
+			&Tile {
+				x: 0,
+				y: 0,
+				width: self.width,
+				height: self.height,
+			},
+			channel,
+		)
+	}
+
+	/// What the viewer shows of `channel` for every pixel, as RGB values row by row from
+	/// the bottom, with depth shaded relative to the nearest surface.
+	pub fn to_display_vec(&self, channel: DisplayChannel) -> Vec<f32> {
+		let mut v = self.to_vec(channel);
+		if channel == DisplayChannel::Depth {
+			let nearest_depth = self.nearest_depth();
+			for value in &mut v {
+				*value = if *value > 0.0 { nearest_depth / *value } else { 0.0 };
+			}
+		}
+		v
+	}
+
+	/// RGB values of `channel` for the pixels in `region`, row by row from the bottom.
+	/// Depth is the raw distance in every component.
+	pub fn region_to_vec(&self, region: &Tile, channel: DisplayChannel) -> Vec<f32> {
+		let mut v = Vec::with_capacity(region.width * region.height * 3);
+		for index in region.pixels(self.width) {
+			let cell = &self.data[index];
+			let vector_cell = match channel {
+				DisplayChannel::Beauty => cell.get_content(),
+				DisplayChannel::Normals => cell.get_surface().normal * 0.5 + V3::new(0.5, 0.5, 0.5),
+				DisplayChannel::Depth => {
+					let depth = cell.get_surface().depth;
+					V3::new(depth, depth, depth)
+				}
+				DisplayChannel::Albedo => cell.get_surface().albedo,
+			};
 			v.push(vector_cell.x);
 			v.push(vector_cell.y);
 			v.push(vector_cell.z);
 		}
 		v
 	}
+
+	/// Distance to the nearest surface any pixel has seen since the last reset, infinite
+	/// if none. Kept as depths are written rather than found by a pass over every pixel.
+	pub fn nearest_depth(&self) -> f32 {
+		self.nearest_depth.load()
+	}
+
+	/// Forget the nearest depth, e.g. when a render starts again from somewhere else.
+	pub fn reset_nearest_depth(&self) {
+		self.nearest_depth.store(f32::INFINITY);
+	}
 }
 
 pub type V3 = Vector3<f32>;
 pub type V2 = Vector2<f32>;
+pub type M3 = Matrix3<f32>;
+pub type M4 = Matrix4<f32>;
 
 #[derive(Debug)]
 pub struct Ray {
 	pub origin: V3,
 	pub direction: V3,
+	// point in the frame, from 0 to 1, the ray samples moving objects at
+	pub time: f32,
+}
+
+impl Ray {
+	pub fn at(&self, t: f32) -> V3 {
+		self.origin + self.direction * t
+	}
+}
+
+/// Everything the shading code needs to know about a ray-surface intersection.
+#[derive(Debug, Clone, Copy)]
+pub struct HitRecord {
+	// distance along the ray
+	pub t: f32,
+	pub point: V3,
+	// hit point in the space of the innermost geometry, for solid textures
+	pub object_point: V3,
+	// true surface normal, as wound by the shape
+	pub geometric_normal: V3,
+	// normal used for lighting, may be interpolated or perturbed
+	pub shading_normal: V3,
+	pub uv: V2,
+	// rate of change of the hit point with respect to u and v, for normal and bump mapping
+	pub tangent: V3,
+	pub bitangent: V3,
+	// the same in the space of object_point
+	pub object_tangent: V3,
+	pub object_bitangent: V3,
+	// whether the ray hit the side the geometric normal points out of
+	pub front_face: bool,
+	// index of the object in the scene
+	pub object_id: usize,
+}
+
+impl HitRecord {
+	pub fn new(ray: &Ray, t: f32, normal: V3, uv: V2) -> HitRecord {
+		// shapes with a real parameterisation overwrite this
+		let tangent = normal.perpendicular();
+
+		HitRecord {
+			t,
+			point: ray.at(t),
+			object_point: ray.at(t),
+			geometric_normal: normal,
+			shading_normal: normal,
+			uv,
+			tangent,
+			bitangent: normal.cross(tangent),
+			object_tangent: tangent,
+			object_bitangent: normal.cross(tangent),
+			front_face: ray.direction.dot(normal) < 0.0,
+			object_id: 0,
+		}
+	}
+
+	pub fn set_tangents(&mut self, tangent: V3, bitangent: V3) {
+		self.tangent = tangent;
+		self.bitangent = bitangent;
+		self.object_tangent = tangent;
+		self.object_bitangent = bitangent;
+	}
 }
@@ -1,4 +1,6 @@
 use cgmath::prelude::*;
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU32, Ordering};
 use types::*;
 
 pub enum QuadraticRoot {
@@ -15,22 +17,123 @@ pub fn solve_quadratic(a: f32, b: f32, c: f32) -> QuadraticRoot {
 		QuadraticRoot::None
 	} else if discriminant.abs() < EPSILON {
 		// only 1 root
-		QuadraticRoot::One((-b + discriminant.sqrt()) / 2.0 * a)
+		QuadraticRoot::One((-b + discriminant.sqrt()) / (2.0 * a))
 	} else {
 		// two real roots
 		QuadraticRoot::Two(
-			(-b + discriminant.sqrt()) / 2.0 * a,
-			(-b - discriminant.sqrt()) / 2.0 * a,
+			(-b + discriminant.sqrt()) / (2.0 * a),
+			(-b - discriminant.sqrt()) / (2.0 * a),
 		)
 	}
 }
 
+// perceived brightness of a linear color
+pub fn luminance(color: Color) -> f32 {
+	0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+}
+
+// uniform direction from a sample in [0, 1]^2
+pub fn sphere_direction(sample: V2) -> V3 {
+	let z = 1.0 - 2.0 * sample.x;
+	let radius = (1.0 - z * z).max(0.0).sqrt();
+	let phi = 2.0 * PI * sample.y;
+	V3::new(radius * phi.cos(), radius * phi.sin(), z)
+}
+
+// uniform point inside the unit sphere, from a direction sample and a distance sample
+pub fn point_in_unit_sphere(direction: V2, distance: f32) -> V3 {
+	sphere_direction(direction) * distance.cbrt()
+}
+
 pub trait V3Extensions {
-	fn reflect(self, V3) -> V3;
+	fn reflect(self, normal: V3) -> V3;
+	fn perpendicular(self) -> V3;
 }
 
 impl V3Extensions for V3 {
 	fn reflect(self, normal: V3) -> V3 {
 		self - 2.0 * normal.dot(self) * normal
 	}
+
+	// some unit vector at right angles to this one
+	fn perpendicular(self) -> V3 {
+		let other = if self.x.abs() < 0.9 {
+			V3::unit_x()
+		} else {
+			V3::unit_y()
+		};
+		self.cross(other).normalize()
+	}
+}
+
+/// f32 that can be shared between threads, stored as its bits.
+#[derive(Default)]
+pub struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+	pub fn new(value: f32) -> AtomicF32 {
+		AtomicF32(AtomicU32::new(value.to_bits()))
+	}
+
+	pub fn load(&self) -> f32 {
+		f32::from_bits(self.0.load(Ordering::Acquire))
+	}
+
+	pub fn store(&self, value: f32) {
+		self.0.store(value.to_bits(), Ordering::Release)
+	}
+
+	pub fn add(&self, value: f32) {
+		let mut current = self.0.load(Ordering::Relaxed);
+		loop {
+			let new = (f32::from_bits(current) + value).to_bits();
+			match self
+				.0
+				.compare_exchange_weak(current, new, Ordering::AcqRel, Ordering::Relaxed)
+			{
+				Ok(_) => return,
+				Err(actual) => current = actual,
+			}
+		}
+	}
+
+	pub fn min(&self, value: f32) {
+		let mut current = self.0.load(Ordering::Relaxed);
+		while value < f32::from_bits(current) {
+			match self.0.compare_exchange_weak(
+				current,
+				value.to_bits(),
+				Ordering::AcqRel,
+				Ordering::Relaxed,
+			) {
+				Ok(_) => return,
+				Err(actual) => current = actual,
+			}
+		}
+	}
+}
+
+/// Vector of three `AtomicF32`s. Each component is atomic on its own, so a load racing
+/// a store can see some components from before it and some from after.
+#[derive(Default)]
+pub struct AtomicV3([AtomicF32; 3]);
+
+impl AtomicV3 {
+	pub fn new(value: V3) -> AtomicV3 {
+		AtomicV3([
+			AtomicF32::new(value.x),
+			AtomicF32::new(value.y),
+			AtomicF32::new(value.z),
+		])
+	}
+
+	pub fn load(&self) -> V3 {
+		V3::new(self.0[0].load(), self.0[1].load(), self.0[2].load())
+	}
+
+	pub fn store(&self, value: V3) {
+		self.0[0].store(value.x);
+		self.0[1].store(value.y);
+		self.0[2].store(value.z);
+	}
 }
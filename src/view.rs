@@ -0,0 +1,144 @@
+use glium::glutin::{ElementState, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+use types::*;
+
+// change in zoom per line scrolled
+const ZOOM_STEP: f32 = 1.25;
+// pixels scrolled on a touchpad that count as one line
+const PIXELS_PER_LINE: f32 = 50.0;
+// closest zoom, in window pixels per image pixel
+const MAX_PIXEL_SCALE: f32 = 64.0;
+
+/// Where the image sits in the window. It is letterboxed to keep its aspect ratio, zoomed
+/// with the scroll wheel about the cursor and panned by dragging with the right mouse
+/// button. Home fits the whole image in the window again and Z shows it at 1:1.
+pub struct View {
+	// relative to the image fitting the window
+	zoom: f32,
+	// point of the image at the centre of the window, in pixels from its bottom left
+	centre: V2,
+	// in pixels
+	window: (u32, u32),
+	image: (u32, u32),
+	panning: bool,
+	cursor: Option<(f64, f64)>,
+}
+
+impl View {
+	/// View of a `width` by `height` image, fitting the window.
+	pub fn new(width: usize, height: usize) -> View {
+		View {
+			zoom: 1.0,
+			centre: V2::new(width as f32 / 2.0, height as f32 / 2.0),
+			window: (width as u32, height as u32),
+			image: (width as u32, height as u32),
+			panning: false,
+			cursor: None,
+		}
+	}
+
+	/// Show a `width` by `height` image instead, fitting it in the window again.
+	pub fn set_image_size(&mut self, width: usize, height: usize) {
+		self.image = (width as u32, height as u32);
+		self.fit();
+	}
+
+	pub fn set_window_size(&mut self, window: (u32, u32)) {
+		self.window = (window.0.max(1), window.1.max(1));
+	}
+
+	pub fn handle_event(&mut self, event: &WindowEvent) {
+		match *event {
+			WindowEvent::Resized(width, height) => self.set_window_size((width, height)),
+			WindowEvent::KeyboardInput { input, .. } if input.state == ElementState::Pressed => {
+				match input.virtual_keycode {
+					Some(VirtualKeyCode::Home) => self.fit(),
+					Some(VirtualKeyCode::Z) => {
+						let zoom = 1.0 / self.fit_scale();
+						self.zoom_about_centre(zoom);
+					}
+					_ => {}
+				}
+			}
+			WindowEvent::MouseWheel { delta, .. } => {
+				let lines = match delta {
+					MouseScrollDelta::LineDelta(_, y) => y,
+					MouseScrollDelta::PixelDelta(_, y) => y / PIXELS_PER_LINE,
+				};
+				let zoom = self.zoom * ZOOM_STEP.powf(lines);
+				match self.cursor {
+					Some(cursor) => self.zoom_about(zoom, cursor),
+					None => self.zoom_about_centre(zoom),
+				}
+			}
+			WindowEvent::MouseInput {
+				state,
+				button: MouseButton::Right,
+				..
+			} => {
+				self.panning = state == ElementState::Pressed;
+			}
+			WindowEvent::CursorMoved { position, .. } => {
+				if let Some((x, y)) = self.cursor.filter(|_| self.panning) {
+					// the window's y axis points down, the image's up
+					let scale = self.pixel_scale();
+					self.centre.x -= (position.0 - x) as f32 / scale;
+					self.centre.y += (position.1 - y) as f32 / scale;
+				}
+				self.cursor = Some(position);
+			}
+			WindowEvent::Focused(false) => self.panning = false,
+			_ => {}
+		}
+	}
+
+	/// Scale and offset, as (x scale, y scale, x offset, y offset), taking the corners of
+	/// the image in normalised device coordinates to where they are drawn in the window.
+	pub fn transform(&self) -> [f32; 4] {
+		let scale = self.pixel_scale();
+		let (width, height) = (self.window.0 as f32, self.window.1 as f32);
+		let (image_width, image_height) = (self.image.0 as f32, self.image.1 as f32);
+
+		[
+			scale * image_width / width,
+			scale * image_height / height,
+			scale * (image_width - 2.0 * self.centre.x) / width,
+			scale * (image_height - 2.0 * self.centre.y) / height,
+		]
+	}
+
+	fn fit(&mut self) {
+		self.zoom = 1.0;
+		self.centre = V2::new(self.image.0 as f32 / 2.0, self.image.1 as f32 / 2.0);
+	}
+
+	// window pixels per image pixel with the whole image fitting in the window
+	fn fit_scale(&self) -> f32 {
+		(self.window.0 as f32 / self.image.0 as f32).min(self.window.1 as f32 / self.image.1 as f32)
+	}
+
+	// window pixels per image pixel
+	fn pixel_scale(&self) -> f32 {
+		self.fit_scale() * self.zoom
+	}
+
+	fn zoom_about_centre(&mut self, zoom: f32) {
+		let centre = (self.window.0 as f64 / 2.0, self.window.1 as f64 / 2.0);
+		self.zoom_about(zoom, centre);
+	}
+
+	// change the zoom, keeping the point of the image under `cursor` where it is
+	fn zoom_about(&mut self, zoom: f32, cursor: (f64, f64)) {
+		let from_centre = V2::new(
+			cursor.0 as f32 - self.window.0 as f32 / 2.0,
+			self.window.1 as f32 / 2.0 - cursor.1 as f32,
+		);
+		let under_cursor = self.centre + from_centre / self.pixel_scale();
+
+		// no smaller than fitting the window, or 1:1 if that is smaller still
+		let fit_scale = self.fit_scale();
+		self.zoom = zoom
+			.min(MAX_PIXEL_SCALE / fit_scale)
+			.max(1.0f32.min(1.0 / fit_scale));
+		self.centre = under_cursor - from_centre / self.pixel_scale();
+	}
+}